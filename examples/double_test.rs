@@ -5,9 +5,9 @@ async fn main() {
     let t = std::time::Instant::now();
 
     // Can now instantiate an [Array] with macros.
-    let array1 = array!(&[3, 1, 1, 1], &[1u32, 6u32, 5u32]);
-    let array2 = array!(&[3, 1, 1, 1], &[4u32, 12u32, 10u32]);
-    // [double_test] calls [test_fn()] in Executor which Doubles all the values in the array.
+    let mut array1 = array!(&[3, 1, 1, 1], &[1u32, 6u32, 5u32]);
+    let mut array2 = array!(&[3, 1, 1, 1], &[4u32, 12u32, 10u32]);
+    // [Array::double_test] runs the `double` kernel in place, doubling every value of the array.
     let test_1 = std::time::Instant::now();
     let res1 = array1.double_test().await.unwrap();
     println!("Result for {} = {:?}; time = {:?}", array1.id(), res1, test_1.elapsed());
@@ -0,0 +1,340 @@
+//! Procedural macros for [Luma](https://crates.io/crates/luma). Use them through the `luma` crate, which re-exports them.
+extern crate proc_macro;
+
+mod translate;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{FnArg, Ident, ItemFn, Pat, ReturnType, Type};
+use translate::{scalar_type, Translator, INDEX, PARAMS};
+
+/// Turns a function written in a subset of Rust into a compute kernel, replacing it with an async launcher of the same name.
+///
+/// Parameters can be `&[T]` slices the kernel reads, `&mut [T]` slices it writes, and scalars, where `T` is `f32`, `u32`
+/// or `i32`. Kernels work on 32 bit words, so `usize` and other wider types are rejected, in parameters and in the
+/// body alike, rather than truncated. The launcher takes an [Array] for every slice, in the same position, and the
+/// scalars as they are. It runs the body once per element of the first `&mut` slice, which the body finds with
+/// `index()`; invocations past its length never run. Slices know their `.len()`.
+///
+/// The body can use `let`, `let mut`, assignments, `if`, `while`, `loop`, `for` over ranges, indexing, arithmetic,
+/// `as` casts between scalars and the float methods WGSL has a builtin for, such as `.sqrt()` and `.mul_add()`.
+/// It's translated to WGSL and validated while the crate compiles, so type errors show up as compile errors.
+/// An `if` used as a value becomes WGSL's `select`, which evaluates both branches. Expressions in kernels can't have
+/// side effects, so this only costs the work of the branch not taken; guard expensive ones with an `if` statement.
+///
+/// The launcher returns [Error::DtypeMismatch] for an array that doesn't hold the slice's element type, and needs
+/// the GPU backend. The kernel is registered with each [Context] on its first launch there.
+///
+/// [Array]: https://docs.rs/luma/latest/luma/struct.Array.html
+/// [Context]: https://docs.rs/luma/latest/luma/struct.Context.html
+/// [Error::DtypeMismatch]: https://docs.rs/luma/latest/luma/enum.Error.html#variant.DtypeMismatch
+#[proc_macro_attribute]
+pub fn kernel(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let attr = TokenStream2::from(attr);
+        return syn::Error::new(attr.span(), "#[kernel] takes no arguments").to_compile_error().into();
+    }
+    let function = syn::parse_macro_input!(item as ItemFn);
+
+    expand(function).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// How a kernel parameter is passed.
+enum Role {
+    Input(&'static str),  // `&[T]`, bound read-only, with the WGSL element type.
+    Output(&'static str), // `&mut [T]`, bound read-write.
+    Scalar(Scalar),       // Passed in the uniform block.
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    F32,
+    U32,
+    I32,
+}
+
+struct Parameter {
+    name: Ident,
+    ty: Type,
+    role: Role,
+}
+
+fn expand(function: ItemFn) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if let Some(token) = signature.asyncness {
+        return Err(syn::Error::new(token.span(), "kernels can't be async; the generated launcher is"));
+    }
+    if let Some(token) = signature.unsafety {
+        return Err(syn::Error::new(token.span(), "kernels can't be unsafe"));
+    }
+    if !signature.generics.params.is_empty() || signature.generics.where_clause.is_some() {
+        return Err(syn::Error::new(signature.generics.span(), "kernels can't be generic"));
+    }
+    if let ReturnType::Type(_, ty) = &signature.output {
+        return Err(syn::Error::new(ty.span(), "kernels can't return a value; write to an output slice instead"));
+    }
+
+    let parameters = signature.inputs.iter().map(parameter).collect::<syn::Result<Vec<_>>>()?;
+    let Some(output) = parameters.iter().find(|parameter| matches!(parameter.role, Role::Output(_))) else {
+        return Err(syn::Error::new(signature.inputs.span(), "kernels need a `&mut [T]` slice to write to"));
+    };
+    let source = generate(&function, &parameters, &output.name)?;
+    validate(&source, signature.ident.span())?;
+
+    Ok(launcher(&function, &parameters, &output.name, &source))
+}
+
+fn parameter(arg: &FnArg) -> syn::Result<Parameter> {
+    let FnArg::Typed(typed) = arg else {
+        return Err(syn::Error::new(arg.span(), "kernels can't take `self`"));
+    };
+    let Pat::Ident(pat) = &*typed.pat else {
+        return Err(syn::Error::new(typed.pat.span(), "kernel parameters must be plain names"));
+    };
+    if pat.by_ref.is_some() || pat.mutability.is_some() || pat.subpat.is_some() {
+        return Err(syn::Error::new(typed.pat.span(), "kernel parameters must be plain names"));
+    }
+
+    let role = match &*typed.ty {
+        Type::Reference(reference) => {
+            let Type::Slice(slice) = &*reference.elem else {
+                return Err(syn::Error::new(reference.elem.span(), "kernels only take references to slices, like `&[f32]`"));
+            };
+            let element = match &*slice.elem {
+                Type::Path(path) => match path.path.get_ident().map(|ident| ident.to_string()).as_deref() {
+                    Some("f32") => "f32",
+                    Some("u32") => "u32",
+                    Some("i32") => "i32",
+                    _ => return Err(syn::Error::new(slice.elem.span(), "slices in kernels hold f32, u32 or i32")),
+                },
+                _ => return Err(syn::Error::new(slice.elem.span(), "slices in kernels hold f32, u32 or i32")),
+            };
+            if reference.mutability.is_some() {
+                Role::Output(element)
+            } else {
+                Role::Input(element)
+            }
+        }
+        ty => Role::Scalar(match ty {
+            Type::Path(path) => match path.path.get_ident().map(|ident| ident.to_string()).as_deref() {
+                Some("f32") => Scalar::F32,
+                Some("u32") => Scalar::U32,
+                Some("i32") => Scalar::I32,
+                Some(wide @ ("usize" | "isize" | "u64" | "i64")) => {
+                    return Err(syn::Error::new(
+                        ty.span(),
+                        format!("kernels take 32 bit scalars, so `{}` would be truncated; take a u32 or i32 instead", wide),
+                    ))
+                }
+                _ => return Err(syn::Error::new(ty.span(), "scalar kernel parameters must be f32, u32 or i32")),
+            },
+            _ => return Err(syn::Error::new(ty.span(), "scalar kernel parameters must be f32, u32 or i32")),
+        }),
+    };
+
+    Ok(Parameter {
+        name: pat.ident.clone(),
+        ty: (*typed.ty).clone(),
+        role,
+    })
+}
+
+/// Builds the kernel source. Following the custom kernel conventions the inputs are bound first, then the outputs,
+/// then the uniform block holding the scalars and the length of every slice.
+fn generate(function: &ItemFn, parameters: &[Parameter], output: &Ident) -> syn::Result<String> {
+    let slices = parameters
+        .iter()
+        .filter(|parameter| !matches!(parameter.role, Role::Scalar(_)))
+        .map(|parameter| parameter.name.to_string())
+        .collect::<Vec<_>>();
+
+    let mut source = String::from("override WORKGROUP_SIZE: u32 = 64;\n\nstruct LumaParams {\n");
+    for parameter in parameters {
+        match parameter.role {
+            Role::Scalar(_) => source += &format!("    {}: {},\n", parameter.name, scalar_type(&parameter.ty)?),
+            _ => source += &format!("    {}_len: u32,\n", parameter.name),
+        }
+    }
+    source += "}\n\n";
+
+    let inputs = parameters.iter().filter_map(|parameter| match parameter.role {
+        Role::Input(element) => Some((&parameter.name, "read", element)),
+        _ => None,
+    });
+    let outputs = parameters.iter().filter_map(|parameter| match parameter.role {
+        Role::Output(element) => Some((&parameter.name, "read_write", element)),
+        _ => None,
+    });
+    let mut binding = 0;
+    for (name, access, element) in inputs.chain(outputs) {
+        source += &format!("@group(0) @binding({}) var<storage, {}> {}: array<{}>;\n", binding, access, name, element);
+        binding += 1;
+    }
+    source += &format!("@group(0) @binding({}) var<uniform> {}: LumaParams;\n\n", binding, PARAMS);
+
+    source += &format!(
+        "@compute\n\
+         @workgroup_size(WORKGROUP_SIZE)\n\
+         fn main(\n    \
+             @builtin(workgroup_id) workgroup_id: vec3<u32>,\n    \
+             @builtin(num_workgroups) num_workgroups: vec3<u32>,\n    \
+             @builtin(local_invocation_index) local_index: u32,\n\
+         ) {{\n    \
+             let {index} = (workgroup_id.x + (workgroup_id.y + workgroup_id.z * num_workgroups.y) * num_workgroups.x) * WORKGROUP_SIZE + local_index;\n    \
+             if ({index} >= {params}.{output}_len) {{\n        return;\n    }}\n",
+        index = INDEX,
+        params = PARAMS,
+        output = output,
+    );
+    for parameter in parameters {
+        if let Role::Scalar(_) = parameter.role {
+            source += &format!("    let {} = {}.{};\n", parameter.name, PARAMS, parameter.name);
+        }
+    }
+    source += &Translator { slices: &slices }.block(&function.block, 1)?;
+    source += "}\n";
+
+    Ok(source)
+}
+
+/// Runs the generated source through naga, so a kernel that wouldn't compile on the device fails the build instead.
+fn validate(source: &str, span: Span) -> syn::Result<()> {
+    let error = |message: String| syn::Error::new(span, format!("kernel doesn't translate to valid WGSL: {}\n\n{}", message, source));
+
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| error(e.emit_to_string(source)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| error(e.emit_to_string(source)))?;
+
+    Ok(())
+}
+
+/// Replaces the kernel with an async function taking an `Array` for every slice.
+fn launcher(function: &ItemFn, parameters: &[Parameter], output: &Ident, source: &str) -> TokenStream2 {
+    let attrs = &function.attrs;
+    let vis = &function.vis;
+    let name = &function.sig.ident;
+    let name_string = name.to_string();
+
+    let arguments = parameters.iter().map(|parameter| {
+        let name = &parameter.name;
+        match &parameter.role {
+            Role::Input(_) => quote!(#name: &::luma::Array),
+            Role::Output(_) => quote!(#name: &mut ::luma::Array),
+            Role::Scalar(_) => {
+                let ty = &parameter.ty;
+                quote!(#name: #ty)
+            }
+        }
+    });
+    let checks = parameters.iter().filter_map(|parameter| {
+        let name = &parameter.name;
+        let dtype = match parameter.role {
+            Role::Input(element) | Role::Output(element) => format_ident!("{}", element.to_uppercase()),
+            Role::Scalar(_) => return None,
+        };
+        Some(quote! {
+            if #name.dtype() != ::luma::DType::#dtype {
+                return ::core::result::Result::Err(::luma::Error::DtypeMismatch {
+                    expected: ::luma::DType::#dtype,
+                    actual: #name.dtype(),
+                });
+            }
+        })
+    });
+    // Same order as the fields of `LumaParams`.
+    let params = parameters.iter().map(|parameter| {
+        let name = &parameter.name;
+        match parameter.role {
+            Role::Scalar(Scalar::F32) => quote!(#name.to_bits()),
+            Role::Scalar(Scalar::U32) => quote!(#name),
+            Role::Scalar(Scalar::I32) => quote!(#name as u32),
+            _ => quote!(#name.len() as u32),
+        }
+    });
+    let inputs = parameters.iter().filter(|parameter| matches!(parameter.role, Role::Input(_))).map(|parameter| &parameter.name);
+    let outputs = parameters.iter().filter(|parameter| matches!(parameter.role, Role::Output(_))).map(|parameter| &parameter.name);
+    let input_count = parameters.iter().filter(|parameter| matches!(parameter.role, Role::Input(_))).count();
+    let output_count = parameters.iter().filter(|parameter| matches!(parameter.role, Role::Output(_))).count();
+    let context = &parameters.iter().find(|parameter| !matches!(parameter.role, Role::Scalar(_))).unwrap().name;
+
+    quote! {
+        #(#attrs)*
+        #vis async fn #name(#(#arguments),*) -> ::core::result::Result<(), ::luma::Error> {
+            const NAME: &str = ::core::concat!(::core::module_path!(), "::", #name_string);
+            const SOURCE: &str = #source;
+
+            #(#checks)*
+            let context = #context.context().clone();
+            let params = [#(#params),*];
+            let len = #output.len();
+            context
+                .ensure_kernel(::luma::Kernel::new(NAME, SOURCE).inputs(#input_count).outputs(#output_count).uniform(true))
+                .await?;
+            if len == 0 {
+                return ::core::result::Result::Ok(());
+            }
+            context.run_kernel(NAME, &[#(#inputs),*], &mut [#(#outputs),*], &params, ::luma::Grid::Elements(len)).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn source(function: ItemFn) -> syn::Result<String> {
+        let parameters = function.sig.inputs.iter().map(parameter).collect::<syn::Result<Vec<_>>>()?;
+        let output = parameters.iter().find(|parameter| matches!(parameter.role, Role::Output(_))).unwrap();
+        let source = generate(&function, &parameters, &output.name)?;
+        validate(&source, Span::call_site())?;
+
+        Ok(source)
+    }
+
+    #[test]
+    fn generated_kernels_validate() {
+        let source = source(parse_quote! {
+            fn saxpy(a: f32, n: u32, x: &[f32], y: &mut [f32], counts: &mut [u32]) {
+                let i = index();
+                y[i] = a.mul_add(x[i], y[i]);
+                let mut count = 0u32;
+                for j in 0..n {
+                    count += j;
+                }
+                counts[i] = if x[i] > 0.0 { count } else { x.len() };
+            }
+        })
+        .unwrap();
+        assert!(source.contains("@group(0) @binding(0) var<storage, read> x: array<f32>;"));
+        assert!(source.contains("@group(0) @binding(1) var<storage, read_write> y: array<f32>;"));
+        assert!(source.contains("@group(0) @binding(2) var<storage, read_write> counts: array<u32>;"));
+        assert!(source.contains("@group(0) @binding(3) var<uniform> luma_params: LumaParams;"));
+        assert!(source.contains("    a: f32,\n    n: u32,\n    x_len: u32,\n    y_len: u32,\n    counts_len: u32,\n"));
+        assert!(source.contains("if (luma_index >= luma_params.y_len)"));
+    }
+
+    #[test]
+    fn type_errors_fail_validation() {
+        let error = source(parse_quote! {
+            fn mix(x: &[f32], y: &mut [u32]) {
+                y[index()] = x[index()];
+            }
+        });
+        assert!(error.unwrap_err().to_string().contains("doesn't translate to valid WGSL"));
+    }
+
+    #[test]
+    fn wide_scalars_are_rejected() {
+        for ty in ["usize", "isize", "u64", "i64"] {
+            let ty = syn::parse_str::<Type>(ty).unwrap();
+            let error = parameter(&parse_quote!(n: #ty)).err().unwrap().to_string();
+            assert!(error.contains("would be truncated"), "{}", error);
+        }
+        let error = parameter(&parse_quote!(n: f64)).err().unwrap().to_string();
+        assert!(error.contains("must be f32, u32 or i32"), "{}", error);
+    }
+}
@@ -0,0 +1,457 @@
+use syn::spanned::Spanned;
+use syn::{BinOp, Block, Expr, Lit, Local, Pat, Stmt, Type, UnOp};
+
+/// Name the flattened invocation index is bound to in the generated `main`.
+pub const INDEX: &str = "luma_index";
+
+/// Name of the uniform block holding the scalar parameters and slice lengths.
+pub const PARAMS: &str = "luma_params";
+
+/// Translates a kernel body, knowing which of the parameters are slices.
+pub struct Translator<'a> {
+    pub slices: &'a [String], // Parameters whose `.len()` is read from the uniform block.
+}
+
+impl Translator<'_> {
+    /// Translates the statements of a kernel body into WGSL, indented by `depth` levels.
+    pub fn block(&self, block: &Block, depth: usize) -> syn::Result<String> {
+        let mut out = String::new();
+        for stmt in &block.stmts {
+            out += &self.statement(stmt, depth)?;
+        }
+
+        Ok(out)
+    }
+
+    fn statement(&self, stmt: &Stmt, depth: usize) -> syn::Result<String> {
+        let pad = indent(depth);
+        match stmt {
+            Stmt::Local(local) => Ok(format!("{}{};\n", pad, self.declaration(local)?)),
+            Stmt::Expr(expr, semi) => match expr {
+                Expr::If(_) | Expr::ForLoop(_) | Expr::While(_) | Expr::Loop(_) | Expr::Block(_) => self.control(expr, depth),
+                Expr::Assign(assign) => Ok(format!("{}{} = {};\n", pad, self.expression(&assign.left)?, self.expression(&assign.right)?)),
+                Expr::Binary(binary) if compound(&binary.op).is_some() => Ok(format!(
+                    "{}{} {} {};\n",
+                    pad,
+                    self.expression(&binary.left)?,
+                    compound(&binary.op).unwrap(),
+                    self.expression(&binary.right)?
+                )),
+                Expr::Break(brk) if brk.label.is_none() && brk.expr.is_none() => Ok(format!("{}break;\n", pad)),
+                Expr::Continue(cont) if cont.label.is_none() => Ok(format!("{}continue;\n", pad)),
+                Expr::Return(ret) if ret.expr.is_none() => Ok(format!("{}return;\n", pad)),
+                Expr::Return(ret) => Err(syn::Error::new(ret.span(), "kernels can't return a value; write to an output slice instead")),
+                _ if semi.is_none() => Err(syn::Error::new(expr.span(), "kernels don't return a value; end the statement with `;`")),
+                _ => Err(syn::Error::new(expr.span(), "only assignments and control flow can be used as statements in a kernel")),
+            },
+            Stmt::Item(item) => Err(syn::Error::new(item.span(), "items can't be declared inside a kernel")),
+            Stmt::Macro(mac) => Err(syn::Error::new(mac.span(), "macros can't be used inside a kernel")),
+        }
+    }
+
+    /// `let` becomes WGSL `let`, `let mut` becomes `var`.
+    fn declaration(&self, local: &Local) -> syn::Result<String> {
+        let (pat, ty) = match &local.pat {
+            Pat::Type(typed) => (&*typed.pat, Some(scalar_type(&typed.ty)?)),
+            pat => (pat, None),
+        };
+        let Pat::Ident(ident) = pat else {
+            return Err(syn::Error::new(pat.span(), "only plain variable names can be bound in a kernel"));
+        };
+        if ident.by_ref.is_some() || ident.subpat.is_some() {
+            return Err(syn::Error::new(pat.span(), "only plain variable names can be bound in a kernel"));
+        }
+
+        let keyword = if ident.mutability.is_some() { "var" } else { "let" };
+        let mut out = format!("{} {}", keyword, ident.ident);
+        if let Some(ty) = ty {
+            out += &format!(": {}", ty);
+        }
+        match &local.init {
+            Some(init) if init.diverge.is_some() => {
+                return Err(syn::Error::new(local.span(), "`let ... else` can't be used in a kernel"));
+            }
+            Some(init) => out += &format!(" = {}", self.expression(&init.expr)?),
+            None if keyword == "let" => {
+                return Err(syn::Error::new(local.span(), "immutable bindings need a value; use `let mut` to assign it later"));
+            }
+            None => {}
+        }
+
+        Ok(out)
+    }
+
+    fn control(&self, expr: &Expr, depth: usize) -> syn::Result<String> {
+        let pad = indent(depth);
+        match expr {
+            Expr::If(expr_if) => {
+                let mut out = format!("{}if ({}) {{\n{}{}}}", pad, self.expression(&expr_if.cond)?, self.block(&expr_if.then_branch, depth + 1)?, pad);
+                match expr_if.else_branch.as_ref().map(|(_, branch)| &**branch) {
+                    None => out += "\n",
+                    Some(Expr::If(_)) => {
+                        let nested = self.control(&expr_if.else_branch.as_ref().unwrap().1, depth)?;
+                        out += &format!(" else {}", nested.trim_start());
+                    }
+                    Some(Expr::Block(else_block)) => {
+                        out += &format!(" else {{\n{}{}}}\n", self.block(&else_block.block, depth + 1)?, pad);
+                    }
+                    Some(other) => return Err(syn::Error::new(other.span(), "unsupported `else` branch")),
+                }
+                Ok(out)
+            }
+            Expr::ForLoop(for_loop) => {
+                let Pat::Ident(var) = &*for_loop.pat else {
+                    return Err(syn::Error::new(for_loop.pat.span(), "loop variables must be plain names"));
+                };
+                let Expr::Range(range) = &*for_loop.expr else {
+                    return Err(syn::Error::new(for_loop.expr.span(), "kernels can only loop over ranges like `a..b`"));
+                };
+                let (Some(start), Some(end)) = (&range.start, &range.end) else {
+                    return Err(syn::Error::new(range.span(), "loop ranges need both a start and an end"));
+                };
+                let comparison = match range.limits {
+                    syn::RangeLimits::HalfOpen(_) => "<",
+                    syn::RangeLimits::Closed(_) => "<=",
+                };
+                let var = &var.ident;
+                let end = self.expression(end)?;
+                let header = format!("{} = {}; {} {} {}; {}++", var, self.expression(start)?, var, comparison, end, var);
+                match &**start {
+                    // WGSL would make an untyped start an i32, where Rust infers the type from the end.
+                    // Declaring the variable from the end first gives it the end's type.
+                    Expr::Lit(lit) if matches!(&lit.lit, Lit::Int(int) if int.suffix().is_empty()) => Ok(format!(
+                        "{pad}{{\n{pad}    var {var} = {end};\n{pad}    for ({header}) {{\n{body}{pad}    }}\n{pad}}}\n",
+                        body = self.block(&for_loop.body, depth + 2)?
+                    )),
+                    _ => Ok(format!("{pad}for (var {header}) {{\n{body}{pad}}}\n", body = self.block(&for_loop.body, depth + 1)?)),
+                }
+            }
+            Expr::While(while_loop) => Ok(format!(
+                "{}while ({}) {{\n{}{}}}\n",
+                pad,
+                self.expression(&while_loop.cond)?,
+                self.block(&while_loop.body, depth + 1)?,
+                pad
+            )),
+            Expr::Loop(inner) => Ok(format!("{}loop {{\n{}{}}}\n", pad, self.block(&inner.body, depth + 1)?, pad)),
+            Expr::Block(inner) => Ok(format!("{}{{\n{}{}}}\n", pad, self.block(&inner.block, depth + 1)?, pad)),
+            _ => unreachable!("only called for control flow"),
+        }
+    }
+
+    /// Translates an expression that produces a value.
+    pub fn expression(&self, expr: &Expr) -> syn::Result<String> {
+        match expr {
+            Expr::Lit(lit) => literal(&lit.lit),
+            Expr::Path(path) => {
+                let segments = path.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+                match segments.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+                    [name] => Ok(name.to_string()),
+                    [.., "f32", "consts", constant] => float_constant(constant).ok_or_else(|| {
+                        syn::Error::new(path.span(), format!("f32::consts::{} isn't available in kernels", constant))
+                    }),
+                    _ => Err(syn::Error::new(path.span(), "only local variables and kernel parameters can be named in a kernel")),
+                }
+            }
+            Expr::Paren(paren) => Ok(format!("({})", self.expression(&paren.expr)?)),
+            Expr::Group(group) => self.expression(&group.expr),
+            Expr::Unary(unary) => match unary.op {
+                UnOp::Neg(_) => Ok(format!("-{}", self.expression(&unary.expr)?)),
+                UnOp::Not(_) => Ok(format!("!{}", self.expression(&unary.expr)?)),
+                _ => Err(syn::Error::new(unary.span(), "dereferencing isn't supported in kernels")),
+            },
+            Expr::Binary(binary) => match binary_operator(&binary.op) {
+                Some(op) => Ok(format!("{} {} {}", self.expression(&binary.left)?, op, self.expression(&binary.right)?)),
+                None => Err(syn::Error::new(binary.span(), "compound assignments can only be used as statements")),
+            },
+            Expr::Index(index) => Ok(format!("{}[{}]", self.expression(&index.expr)?, self.expression(&index.index)?)),
+            Expr::Cast(cast) => Ok(format!("{}({})", scalar_type(&cast.ty)?, self.expression(&cast.expr)?)),
+            Expr::If(expr_if) => {
+                // `if c { a } else { b }` used as a value becomes `select(b, a, c)`.
+                let value = |block: &Block| match block.stmts.as_slice() {
+                    [Stmt::Expr(expr, None)] => self.expression(expr),
+                    _ => Err(syn::Error::new(block.span(), "`if` used as a value must have a single expression in each branch")),
+                };
+                let Some((_, else_branch)) = &expr_if.else_branch else {
+                    return Err(syn::Error::new(expr_if.span(), "`if` used as a value needs an `else` branch"));
+                };
+                let reject = match &**else_branch {
+                    Expr::Block(else_block) => value(&else_block.block)?,
+                    other => self.expression(other)?,
+                };
+                Ok(format!("select({}, {}, {})", reject, value(&expr_if.then_branch)?, self.expression(&expr_if.cond)?))
+            }
+            Expr::Call(call) => {
+                let Expr::Path(function) = &*call.func else {
+                    return Err(syn::Error::new(call.func.span(), "only `index()` and `f32::` functions can be called in a kernel"));
+                };
+                let segments = function.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+                let args = call.args.iter().map(|arg| self.expression(arg)).collect::<syn::Result<Vec<_>>>()?;
+                match segments.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+                    ["index"] if args.is_empty() => Ok(INDEX.to_string()),
+                    [ty, method] if matches!(*ty, "f32" | "u32" | "i32") => match method_name(method) {
+                        Some(name) => Ok(format!("{}({})", name, args.join(", "))),
+                        None => Err(syn::Error::new(call.span(), format!("{}::{} isn't available in kernels", ty, method))),
+                    },
+                    _ => Err(syn::Error::new(call.span(), "only `index()` and `f32::` functions can be called in a kernel")),
+                }
+            }
+            Expr::MethodCall(call) => {
+                let method = call.method.to_string();
+                if method == "len" && call.args.is_empty() {
+                    // Slices are bound whole, so their length comes in through the uniform block.
+                    return match &*call.receiver {
+                        Expr::Path(path) if path.path.get_ident().is_some_and(|ident| self.slices.contains(&ident.to_string())) => {
+                            Ok(format!("{}.{}_len", PARAMS, path.path.get_ident().unwrap()))
+                        }
+                        receiver => Err(syn::Error::new(receiver.span(), "`.len()` can only be called on slice parameters")),
+                    };
+                }
+                let Some(name) = method_name(&method) else {
+                    return Err(syn::Error::new(call.method.span(), format!("`.{}()` isn't available in kernels", method)));
+                };
+                let mut args = vec![self.expression(&call.receiver)?];
+                for arg in &call.args {
+                    args.push(self.expression(arg)?);
+                }
+                Ok(format!("{}({})", name, args.join(", ")))
+            }
+            other => Err(syn::Error::new(other.span(), "this expression isn't supported in kernels")),
+        }
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+/// WGSL spelling of a compound assignment operator.
+fn compound(op: &BinOp) -> Option<&'static str> {
+    Some(match op {
+        BinOp::AddAssign(_) => "+=",
+        BinOp::SubAssign(_) => "-=",
+        BinOp::MulAssign(_) => "*=",
+        BinOp::DivAssign(_) => "/=",
+        BinOp::RemAssign(_) => "%=",
+        BinOp::BitAndAssign(_) => "&=",
+        BinOp::BitOrAssign(_) => "|=",
+        BinOp::BitXorAssign(_) => "^=",
+        BinOp::ShlAssign(_) => "<<=",
+        BinOp::ShrAssign(_) => ">>=",
+        _ => return None,
+    })
+}
+
+fn binary_operator(op: &BinOp) -> Option<&'static str> {
+    Some(match op {
+        BinOp::Add(_) => "+",
+        BinOp::Sub(_) => "-",
+        BinOp::Mul(_) => "*",
+        BinOp::Div(_) => "/",
+        BinOp::Rem(_) => "%",
+        BinOp::And(_) => "&&",
+        BinOp::Or(_) => "||",
+        BinOp::BitAnd(_) => "&",
+        BinOp::BitOr(_) => "|",
+        BinOp::BitXor(_) => "^",
+        BinOp::Shl(_) => "<<",
+        BinOp::Shr(_) => ">>",
+        BinOp::Eq(_) => "==",
+        BinOp::Ne(_) => "!=",
+        BinOp::Lt(_) => "<",
+        BinOp::Le(_) => "<=",
+        BinOp::Gt(_) => ">",
+        BinOp::Ge(_) => ">=",
+        _ => return None,
+    })
+}
+
+/// WGSL builtin for a Rust float or integer method.
+fn method_name(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "abs" => "abs",
+        "min" => "min",
+        "max" => "max",
+        "clamp" => "clamp",
+        "signum" => "sign",
+        "sqrt" => "sqrt",
+        "sin" => "sin",
+        "cos" => "cos",
+        "tan" => "tan",
+        "asin" => "asin",
+        "acos" => "acos",
+        "atan" => "atan",
+        "atan2" => "atan2",
+        "sinh" => "sinh",
+        "cosh" => "cosh",
+        "tanh" => "tanh",
+        "exp" => "exp",
+        "exp2" => "exp2",
+        "ln" => "log",
+        "log2" => "log2",
+        "powf" => "pow",
+        "floor" => "floor",
+        "ceil" => "ceil",
+        "round_ties_even" => "round",
+        "trunc" => "trunc",
+        "fract" => "fract",
+        "mul_add" => "fma",
+        "to_radians" => "radians",
+        "to_degrees" => "degrees",
+        _ => return None,
+    })
+}
+
+fn float_constant(name: &str) -> Option<String> {
+    let value = match name {
+        "PI" => std::f32::consts::PI,
+        "TAU" => std::f32::consts::TAU,
+        "E" => std::f32::consts::E,
+        "SQRT_2" => std::f32::consts::SQRT_2,
+        "LN_2" => std::f32::consts::LN_2,
+        "LN_10" => std::f32::consts::LN_10,
+        _ => return None,
+    };
+
+    Some(format!("{:?}f", value))
+}
+
+fn literal(lit: &Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Bool(b) => Ok(b.value.to_string()),
+        Lit::Int(int) => {
+            let digits = int.base10_digits();
+            match int.suffix() {
+                "" => Ok(digits.to_string()),
+                "u32" => Ok(format!("{}u", digits)),
+                "i32" => Ok(format!("{}i", digits)),
+                "f32" => Ok(format!("{}.0f", digits)),
+                suffix => Err(syn::Error::new(int.span(), format!("`{}` literals aren't supported in kernels", suffix))),
+            }
+        }
+        Lit::Float(float) => {
+            let digits = float.base10_digits();
+            let digits = if digits.contains(['.', 'e', 'E']) { digits.to_string() } else { format!("{}.0", digits) };
+            match float.suffix() {
+                "" => Ok(digits),
+                "f32" => Ok(format!("{}f", digits)),
+                suffix => Err(syn::Error::new(float.span(), format!("`{}` literals aren't supported in kernels", suffix))),
+            }
+        }
+        other => Err(syn::Error::new(other.span(), "only numbers and booleans can be used as literals in kernels")),
+    }
+}
+
+/// WGSL name of a scalar type used in a cast or a `let`.
+pub fn scalar_type(ty: &Type) -> syn::Result<&'static str> {
+    let Type::Path(path) = ty else {
+        return Err(syn::Error::new(ty.span(), "only f32, u32, i32 and bool can be used in kernels"));
+    };
+    match path.path.get_ident().map(|ident| ident.to_string()).as_deref() {
+        Some("f32") => Ok("f32"),
+        Some("u32") => Ok("u32"),
+        Some("i32") => Ok("i32"),
+        Some("bool") => Ok("bool"),
+        Some(wide @ ("usize" | "isize" | "u64" | "i64")) => Err(syn::Error::new(
+            ty.span(),
+            format!("kernels work on 32 bit words, so `{}` would be truncated; use u32 or i32 instead", wide),
+        )),
+        _ => Err(syn::Error::new(ty.span(), "only f32, u32, i32 and bool can be used in kernels")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn translate(block: Block) -> String {
+        let slices = ["x".to_string(), "y".to_string()];
+        Translator { slices: &slices }.block(&block, 0).unwrap()
+    }
+
+    fn error(block: Block) -> String {
+        let slices = ["x".to_string(), "y".to_string()];
+        Translator { slices: &slices }.block(&block, 0).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn bindings() {
+        assert_eq!(translate(parse_quote!({ let a = 1.5; })), "let a = 1.5;\n");
+        assert_eq!(translate(parse_quote!({ let mut a: u32 = 2u32; })), "var a: u32 = 2u;\n");
+        assert_eq!(translate(parse_quote!({ let mut a: f32; })), "var a: f32;\n");
+        assert!(error(parse_quote!({ let a: f32; })).contains("need a value"));
+        assert!(error(parse_quote!({ let (a, b) = (1, 2); })).contains("plain variable names"));
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(translate(parse_quote!({ y[i] = x[i] * 2.0; })), "y[i] = x[i] * 2.0;\n");
+        assert_eq!(translate(parse_quote!({ a += 1; a <<= 2u32; })), "a += 1;\na <<= 2u;\n");
+        assert!(error(parse_quote!({ x[i] })).contains("end the statement"));
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            translate(parse_quote!({ if a < b { a = b; } else if a > c { a = c; } else { a = 0.0; } })),
+            "if (a < b) {\n    a = b;\n} else if (a > c) {\n    a = c;\n} else {\n    a = 0.0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            translate(parse_quote!({ for j in 0..x.len() { s += x[j]; } })),
+            "{\n    var j = luma_params.x_len;\n    for (j = 0; j < luma_params.x_len; j++) {\n        s += x[j];\n    }\n}\n"
+        );
+        assert_eq!(
+            translate(parse_quote!({ for j in 1u32..=n { s += j; } })),
+            "for (var j = 1u; j <= n; j++) {\n    s += j;\n}\n"
+        );
+        assert_eq!(
+            translate(parse_quote!({ while a > 1.0 { a = a / 2.0; } })),
+            "while (a > 1.0) {\n    a = a / 2.0;\n}\n"
+        );
+        assert_eq!(
+            translate(parse_quote!({ loop { if done { break; } continue; } })),
+            "loop {\n    if (done) {\n        break;\n    }\n    continue;\n}\n"
+        );
+        assert!(error(parse_quote!({ for v in x { s += v; } })).contains("ranges"));
+    }
+
+    #[test]
+    fn expressions() {
+        let slices = ["x".to_string()];
+        let translator = Translator { slices: &slices };
+        let expression = |expr: Expr| translator.expression(&expr).unwrap();
+
+        assert_eq!(expression(parse_quote!(index())), INDEX);
+        assert_eq!(expression(parse_quote!(x.len())), "luma_params.x_len");
+        assert_eq!(expression(parse_quote!(i as u32)), "u32(i)");
+        assert_eq!(expression(parse_quote!(-(a as f32))), "-(f32(a))");
+        assert_eq!(expression(parse_quote!(a.mul_add(b, c).sqrt())), "sqrt(fma(a, b, c))");
+        assert_eq!(expression(parse_quote!(f32::max(a, b))), "max(a, b)");
+        assert_eq!(expression(parse_quote!(std::f32::consts::PI)), format!("{:?}f", std::f32::consts::PI));
+        assert_eq!(expression(parse_quote!(if a > b { a } else { b })), "select(b, a, a > b)");
+        assert_eq!(expression(parse_quote!(3f32 + 2.0e3f32)), "3.0f + 2.0e3f");
+        assert_eq!(expression(parse_quote!(-4i32)), "-4i");
+    }
+
+    #[test]
+    fn unsupported_expressions() {
+        let slices = ["x".to_string()];
+        let translator = Translator { slices: &slices };
+        let error = |expr: Expr| translator.expression(&expr).unwrap_err().to_string();
+
+        assert!(error(parse_quote!(a.powi(2))).contains("`.powi()` isn't available"));
+        assert!(error(parse_quote!(a.len())).contains("slice parameters"));
+        assert!(error(parse_quote!(if a { b })).contains("needs an `else`"));
+        assert!(error(parse_quote!(1u64)).contains("`u64` literals"));
+        assert!(error(parse_quote!(1usize)).contains("`usize` literals"));
+        assert!(error(parse_quote!(a as f64)).contains("only f32, u32"));
+        assert!(error(parse_quote!(a as usize)).contains("would be truncated"));
+        assert!(error(parse_quote!(*a)).contains("dereferencing"));
+        assert!(error(parse_quote!(foo(a))).contains("can be called"));
+    }
+}
@@ -0,0 +1,4 @@
+#[test]
+fn rejected_kernels() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
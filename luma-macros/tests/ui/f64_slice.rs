@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn double(x: &[f64], y: &mut [f64]) {
+    y[index()] = x[index()] * 2.0;
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[luma_macros::kernel]
+fn clamp(x: &[f32], y: &mut [f32]) {
+    let i = index();
+    y[i] = if x[i] > 0.0 { x[i] };
+}
+
+fn main() {}
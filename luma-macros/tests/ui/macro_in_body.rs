@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn debug(x: &[f32], y: &mut [f32]) {
+    println!("{}", x[index()]);
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn sum(x: &[f32]) {
+    let i = index();
+}
+
+fn main() {}
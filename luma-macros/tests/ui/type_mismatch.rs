@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn mix(x: &[f32], y: &mut [u32]) {
+    y[index()] = x[index()];
+}
+
+fn main() {}
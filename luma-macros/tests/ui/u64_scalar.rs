@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn offset(n: u64, y: &mut [u32]) {
+    y[index()] = n;
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn square(x: &[f32], y: &mut [f32]) {
+    y[index()] = x[index()].powi(2);
+}
+
+fn main() {}
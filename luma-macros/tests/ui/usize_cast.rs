@@ -0,0 +1,8 @@
+#[luma_macros::kernel]
+fn offset(x: &[u32], y: &mut [u32]) {
+    let i = index();
+    let n = x.len() as usize;
+    y[i] = x[i] + n as u32;
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[luma_macros::kernel]
+fn offset(n: usize, x: &[u32], y: &mut [u32]) {
+    let i = index();
+    y[i] = x[i] + n;
+}
+
+fn main() {}
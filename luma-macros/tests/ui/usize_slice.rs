@@ -0,0 +1,6 @@
+#[luma_macros::kernel]
+fn copy(x: &[usize], y: &mut [u32]) {
+    y[index()] = x[index()] as u32;
+}
+
+fn main() {}
@@ -1,8 +1,32 @@
+override WORKGROUP_SIZE: u32 = 64; // set by the dispatch planner when the pipeline is created
+
+struct Params {
+    len: u32, // number of elements in the data
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+    offset: u32, // index of the first element of the bound chunk within the whole array
+    _chunk_pad0: u32,
+    _chunk_pad1: u32,
+    _chunk_pad2: u32,
+}
+
 @group(0) @binding(0) var<storage, read_write> v_indices: array<u32>; // this is used as both input and output for convenience
-@group(0) @binding(1) var<storage, read> dims: array<u32, 4>; // the dimensions of the data being processed
+@group(0) @binding(1) var<uniform> params: Params;
 
 @compute
-@workgroup_size(1)
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    v_indices[global_id.x] = v_indices[global_id.x] * 2;
+@workgroup_size(WORKGROUP_SIZE)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    // Large arrays are spread over a 3D grid of workgroups; flatten it back into an element index.
+    let local = (workgroup_id.x + (workgroup_id.y + workgroup_id.z * num_workgroups.y) * num_workgroups.x) * WORKGROUP_SIZE + local_index;
+    // Arrays too large to bind at once run a chunk at a time; `i` is the index within the whole array.
+    let i = params.offset + local;
+    if (local >= arrayLength(&v_indices) || i >= params.len) {
+        return;
+    }
+    v_indices[local] = v_indices[local] * 2;
 }
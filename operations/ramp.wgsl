@@ -0,0 +1,42 @@
+override WORKGROUP_SIZE: u32 = 64; // set by the dispatch planner when the pipeline is created
+
+struct Params {
+    start: u32, // raw bit pattern of the first value
+    step: u32,  // raw bit pattern of the increment
+    len: u32,   // number of elements in the output
+    dtype: u32, // 0 = f32, 1 = u32, 2 = i32
+    offset: u32, // index of the first element of the bound chunk within the whole array
+    _chunk_pad0: u32,
+    _chunk_pad1: u32,
+    _chunk_pad2: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> output: array<u32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    // Large arrays are spread over a 3D grid of workgroups; flatten it back into an element index.
+    let local = (workgroup_id.x + (workgroup_id.y + workgroup_id.z * num_workgroups.y) * num_workgroups.x) * WORKGROUP_SIZE + local_index;
+    // Arrays too large to bind at once run a chunk at a time; `i` is the index within the whole array.
+    let i = params.offset + local;
+    if (local >= arrayLength(&output) || i >= params.len) {
+        return;
+    }
+    switch params.dtype {
+        case 0u: {
+            output[local] = bitcast<u32>(bitcast<f32>(params.start) + f32(i) * bitcast<f32>(params.step));
+        }
+        case 1u: {
+            output[local] = params.start + i * params.step;
+        }
+        default: {
+            output[local] = bitcast<u32>(bitcast<i32>(params.start) + i32(i) * bitcast<i32>(params.step));
+        }
+    }
+}
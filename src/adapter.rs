@@ -0,0 +1,163 @@
+use crate::context::ContextBuilder;
+use crate::error::Error;
+use log::debug;
+use std::str::FromStr;
+use wgpu::{Adapter, AdapterInfo, Backend, Backends, DeviceType, InstanceDescriptor, InstanceFlags, Limits};
+
+/// Environment variable that overrides the adapter picked by every [crate::ContextBuilder].
+/// Parsed with [AdapterSelector::from_str].
+pub const ADAPTER_ENV: &str = "LUMA_ADAPTER";
+
+/// An adapter Luma can run on, as listed by [adapters].
+#[derive(Debug, Clone)]
+pub struct AdapterDescription {
+    /// Position in the list returned by [adapters]; what [AdapterSelector::Index] refers to.
+    pub index: usize,
+    pub info: AdapterInfo,
+    pub limits: Limits,
+}
+
+/// Which adapter a [crate::Context] should be created on.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AdapterSelector {
+    /// Whatever wgpu picks for the builder's power preference.
+    #[default]
+    Default,
+    /// The adapter at this position in [adapters].
+    Index(usize),
+    /// The first adapter whose name contains this, ignoring case.
+    Name(String),
+    /// The first adapter on this backend.
+    Backend(Backend),
+    /// The first adapter of this kind.
+    DeviceType(DeviceType),
+    /// The software fallback adapter, for machines without a GPU such as headless CI.
+    Fallback,
+}
+
+impl FromStr for AdapterSelector {
+    type Err = Error;
+
+    /// Parses the format of [ADAPTER_ENV]: an index, `fallback`, a backend (`vulkan`, `metal`, `dx12`, `gl`),
+    /// a device type (`discrete`, `integrated`, `virtual`, `cpu`) or otherwise part of an adapter name.
+    ///
+    /// # Example
+    /// ```
+    /// use luma::{AdapterSelector, Backend};
+    ///
+    /// assert_eq!("1".parse::<AdapterSelector>().unwrap(), AdapterSelector::Index(1));
+    /// assert_eq!("Vulkan".parse::<AdapterSelector>().unwrap(), AdapterSelector::Backend(Backend::Vulkan));
+    /// assert_eq!("RTX".parse::<AdapterSelector>().unwrap(), AdapterSelector::Name("RTX".into()));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::InvalidArgument("adapter selector is empty".into()));
+        }
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(AdapterSelector::Index(index));
+        }
+
+        Ok(match s.to_lowercase().as_str() {
+            "default" => AdapterSelector::Default,
+            "fallback" | "software" => AdapterSelector::Fallback,
+            "vulkan" => AdapterSelector::Backend(Backend::Vulkan),
+            "metal" => AdapterSelector::Backend(Backend::Metal),
+            "dx12" => AdapterSelector::Backend(Backend::Dx12),
+            "gl" => AdapterSelector::Backend(Backend::Gl),
+            "discrete" => AdapterSelector::DeviceType(DeviceType::DiscreteGpu),
+            "integrated" => AdapterSelector::DeviceType(DeviceType::IntegratedGpu),
+            "virtual" => AdapterSelector::DeviceType(DeviceType::VirtualGpu),
+            "cpu" => AdapterSelector::DeviceType(DeviceType::Cpu),
+            _ => AdapterSelector::Name(s.to_string()),
+        })
+    }
+}
+
+/// Lists every adapter on the system, from all backends.
+///
+/// # Example
+/// ```no_run
+/// for adapter in luma::adapters() {
+///     println!("{}: {} ({:?})", adapter.index, adapter.info.name, adapter.info.backend);
+/// }
+/// ```
+pub fn adapters() -> Vec<AdapterDescription> {
+    create_instance(Backends::all())
+        .enumerate_adapters(Backends::all())
+        .into_iter()
+        .enumerate()
+        .map(|(index, adapter)| AdapterDescription {
+            index,
+            info: adapter.get_info(),
+            limits: adapter.limits(),
+        })
+        .collect()
+}
+
+/// Creates a [wgpu::Instance] that can hand out adapters from `backends`.
+fn create_instance(backends: Backends) -> wgpu::Instance {
+    // Creates adapters and surfaces using the information in the ```InstanceDescriptor```
+    wgpu::Instance::new(&InstanceDescriptor {
+        backends,
+        backend_options: wgpu::BackendOptions {
+            gl: wgpu::GlBackendOptions {
+                gles_minor_version: Default::default(), // Select which minor version of Open GL to use.
+            },
+            dx12: wgpu::Dx12BackendOptions {
+                shader_compiler: Default::default(),
+            }
+        },
+        flags: InstanceFlags::empty(), // Instance flags for debugging.
+    })
+}
+
+/// Finds the adapter `options` asks for. [ADAPTER_ENV] takes precedence over the builder's selector.
+pub(crate) async fn select_adapter(options: &ContextBuilder) -> Result<Adapter, Error> {
+    let selector = match std::env::var(ADAPTER_ENV) {
+        Ok(value) => {
+            let selector = value.parse::<AdapterSelector>()?;
+            debug!("{} overrides the adapter selection with {:?}", ADAPTER_ENV, selector);
+            selector
+        }
+        Err(_) => options.adapter.clone(),
+    };
+
+    let matching = |predicate: &dyn Fn(&AdapterInfo) -> bool| {
+        create_instance(options.backends)
+            .enumerate_adapters(options.backends)
+            .into_iter()
+            .find(|adapter| predicate(&adapter.get_info()))
+            .ok_or(Error::NoAdapter)
+    };
+
+    match selector {
+        AdapterSelector::Default => request_adapter(options, options.force_fallback_adapter).await,
+        AdapterSelector::Fallback => request_adapter(options, true).await,
+        // Indices refer to `adapters()`, which enumerates every backend.
+        AdapterSelector::Index(index) => create_instance(Backends::all())
+            .enumerate_adapters(Backends::all())
+            .into_iter()
+            .nth(index)
+            .ok_or(Error::NoAdapter),
+        AdapterSelector::Name(name) => {
+            let name = name.to_lowercase();
+            matching(&|info| info.name.to_lowercase().contains(&name))
+        }
+        AdapterSelector::Backend(backend) => matching(&|info| info.backend == backend),
+        AdapterSelector::DeviceType(device_type) => matching(&|info| info.device_type == device_type),
+    }
+}
+
+/// Lets wgpu pick an adapter for the builder's power preference.
+async fn request_adapter(options: &ContextBuilder, force_fallback_adapter: bool) -> Result<Adapter, Error> {
+    // Gives us a handle to all gpu compute adapters with the given ```RequestAdapterOptions```
+    create_instance(options.backends)
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: options.power_preference,
+            force_fallback_adapter, // If true, will force WGPU to use an adapter that is supported by all hardware.
+            compatible_surface: None, // If given a surface (like a window / display) it will return adapters that can present to that surface.
+        })
+        .await
+        .ok_or(Error::NoAdapter)
+}
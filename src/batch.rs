@@ -0,0 +1,63 @@
+use crate::context::Context;
+use crate::error::Error;
+use log::error;
+
+/// Records the work done on a [Context] into a single command buffer, submitted once the batch ends.
+/// Submitting and waiting for every operation separately costs more than a small kernel takes to run, so
+/// pipelines of many small operations run much faster batched. Started with [Context::batch].
+///
+/// While any batch is open, every dispatch and copy on the context is held back, whichever task it comes from.
+/// Reading an array back submits what has been recorded so far, since the read has to see the results.
+/// Dropping a batch without [CommandBatch::submit]ting it still submits its work, just without waiting for it.
+///
+/// # Example
+/// ```no_run
+/// # async fn example(ctx: luma::Context, a: luma::Array, b: luma::Array) -> Result<(), luma::Error> {
+/// let batch = ctx.batch();
+/// let sum = a.add(&b).await?;
+/// let ramp = ctx.arange(0.0f32, 4.0, 1.0).await?;
+/// sum.eval().await?;
+/// batch.submit().await?; // Both kernels run from one submission.
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use = "a batch holds back the work on its context until it is submitted or dropped"]
+pub struct CommandBatch {
+    context: Context,
+    open: bool,
+}
+
+impl CommandBatch {
+    pub(crate) fn new(context: &Context) -> Self {
+        context.executor.begin_batch();
+
+        CommandBatch {
+            context: context.clone(),
+            open: true,
+        }
+    }
+
+    /// The [Context] the batch records.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Submits everything recorded since the batch was started and waits until the device has finished it.
+    /// Errors in any of the recorded commands are reported here. If other batches are still open on the
+    /// context, the work is only submitted once the last of them ends.
+    pub async fn submit(mut self) -> Result<(), Error> {
+        self.open = false;
+        self.context.executor.finish_batch().await
+    }
+}
+
+impl Drop for CommandBatch {
+    fn drop(&mut self) {
+        if self.open {
+            if let Err(e) = self.context.executor.end_batch() {
+                error!("Could not submit dropped command batch: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Synchronous versions of the [crate::Context] and [crate::Array] APIs, for programs and tests that don't run an
+//! async executor.
+//!
+//! Every call blocks the calling thread until its result is ready. Don't use them from inside async code, where
+//! they would stall the executor; call the async API there instead.
+//!
+//! # Example
+//! ```
+//! use luma::blocking::Context;
+//!
+//! let ctx = Context::build(luma::Context::builder().backend(luma::BackendKind::Cpu)).unwrap();
+//! let a = ctx.array(&[3, 1, 1, 1], &[1.0f32, 2.0, 3.0]).unwrap();
+//! let b = a.map("x * 10.0").unwrap();
+//! assert_eq!(a.add(&b).unwrap().to_vec::<f32>().unwrap(), vec![11.0, 22.0, 33.0]);
+//! ```
+
+use crate::context::ContextBuilder;
+use crate::dtype::Element;
+use crate::error::Error;
+use crate::kernel::{Grid, Kernel};
+use crate::utils::Nested;
+use std::future::Future;
+use std::ops::Range;
+use std::ops::Deref;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+pub use crate::__array_blocking as array;
+
+/// Runs `future` to completion on the calling thread, parking it whenever the future has to wait.
+/// Works for any future, so it also covers the parts of the API without a blocking version here.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            // Wake-ups that happened since the poll make `park` return at once.
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A [crate::Context] whose operations block instead of returning futures, and whose arrays are blocking [Array]s.
+/// Dereferences to the wrapped context for everything that doesn't have to wait, like [crate::Context::memory_stats].
+#[derive(Debug, Clone)]
+pub struct Context(crate::Context);
+
+impl From<crate::Context> for Context {
+    fn from(ctx: crate::Context) -> Self {
+        Context(ctx)
+    }
+}
+
+impl Deref for Context {
+    type Target = crate::Context;
+
+    fn deref(&self) -> &crate::Context {
+        &self.0
+    }
+}
+
+impl Context {
+    /// Builds a context with the settings of `builder`. See [ContextBuilder::build].
+    pub fn build(builder: ContextBuilder) -> Result<Self, Error> {
+        block_on(builder.build()).map(Context)
+    }
+
+    /// See [crate::Context::new].
+    pub fn new() -> Result<Self, Error> {
+        block_on(crate::Context::new()).map(Context)
+    }
+
+    /// See [crate::Context::global].
+    pub fn global() -> Result<Self, Error> {
+        block_on(crate::Context::global()).map(Context)
+    }
+
+    /// See [crate::Context::prewarm].
+    pub fn prewarm(&self, names: &[&str]) -> Result<(), Error> {
+        block_on(self.0.prewarm(names))
+    }
+
+    /// See [crate::Context::register_kernel].
+    pub fn register_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        block_on(self.0.register_kernel(kernel))
+    }
+
+    /// See [crate::Context::ensure_kernel].
+    pub fn ensure_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        block_on(self.0.ensure_kernel(kernel))
+    }
+
+    /// See [crate::Context::run_kernel].
+    pub fn run_kernel(&self, name: &str, inputs: &[&Array], outputs: &mut [&mut Array], params: &[u32], grid: Grid) -> Result<(), Error> {
+        let inputs = inputs.iter().map(|input| &input.0).collect::<Vec<_>>();
+        let mut outputs = outputs.iter_mut().map(|output| &mut output.0).collect::<Vec<_>>();
+
+        block_on(self.0.run_kernel(name, &inputs, &mut outputs, params, grid))
+    }
+
+    /// See [crate::Context::array].
+    pub fn array<T: Element>(&self, dimensions: &[usize; 4], data: &[T]) -> Result<Array, Error> {
+        block_on(self.0.array(dimensions, data)).map(Array)
+    }
+
+    /// See [crate::Context::from_nested].
+    pub fn from_nested<N: Nested>(&self, data: N) -> Result<Array, Error> {
+        block_on(self.0.from_nested(data)).map(Array)
+    }
+
+    /// See [crate::Context::zeros].
+    pub fn zeros<T: Element>(&self, dimensions: &[usize; 4]) -> Result<Array, Error> {
+        block_on(self.0.zeros::<T>(dimensions)).map(Array)
+    }
+
+    /// See [crate::Context::ones].
+    pub fn ones<T: Element>(&self, dimensions: &[usize; 4]) -> Result<Array, Error> {
+        block_on(self.0.ones::<T>(dimensions)).map(Array)
+    }
+
+    /// See [crate::Context::full].
+    pub fn full<T: Element>(&self, dimensions: &[usize; 4], value: T) -> Result<Array, Error> {
+        block_on(self.0.full(dimensions, value)).map(Array)
+    }
+
+    /// See [crate::Context::arange].
+    pub fn arange<T: Element>(&self, start: T, stop: T, step: T) -> Result<Array, Error> {
+        block_on(self.0.arange(start, stop, step)).map(Array)
+    }
+
+    /// See [crate::Context::linspace].
+    pub fn linspace(&self, start: f32, stop: f32, num: usize) -> Result<Array, Error> {
+        block_on(self.0.linspace(start, stop, num)).map(Array)
+    }
+
+    /// See [crate::Context::eye].
+    pub fn eye<T: Element>(&self, n: usize) -> Result<Array, Error> {
+        block_on(self.0.eye::<T>(n)).map(Array)
+    }
+
+    /// The wrapped [crate::Context], for use with the async API.
+    pub fn into_inner(self) -> crate::Context {
+        self.0
+    }
+}
+
+/// An [crate::Array] whose operations block instead of returning futures.
+/// Dereferences to the wrapped array for everything that doesn't have to wait, like [crate::Array::dimensions].
+#[derive(Debug, Clone)]
+pub struct Array(crate::Array);
+
+impl From<crate::Array> for Array {
+    fn from(array: crate::Array) -> Self {
+        Array(array)
+    }
+}
+
+impl Deref for Array {
+    type Target = crate::Array;
+
+    fn deref(&self) -> &crate::Array {
+        &self.0
+    }
+}
+
+impl Array {
+    /// See [crate::Array::new].
+    pub fn new<T: Element>(dimensions: &[usize; 4], data: &[T]) -> Result<Self, Error> {
+        block_on(crate::Array::new(dimensions, data)).map(Array)
+    }
+
+    /// See [crate::Array::from_nested].
+    pub fn from_nested<N: Nested>(data: N) -> Result<Self, Error> {
+        block_on(crate::Array::from_nested(data)).map(Array)
+    }
+
+    /// See [crate::Array::zeros].
+    pub fn zeros<T: Element>(dimensions: &[usize; 4]) -> Result<Self, Error> {
+        block_on(crate::Array::zeros::<T>(dimensions)).map(Array)
+    }
+
+    /// See [crate::Array::ones].
+    pub fn ones<T: Element>(dimensions: &[usize; 4]) -> Result<Self, Error> {
+        block_on(crate::Array::ones::<T>(dimensions)).map(Array)
+    }
+
+    /// See [crate::Array::full].
+    pub fn full<T: Element>(dimensions: &[usize; 4], value: T) -> Result<Self, Error> {
+        block_on(crate::Array::full(dimensions, value)).map(Array)
+    }
+
+    /// See [crate::Array::zeros_like].
+    pub fn zeros_like(other: &Array) -> Result<Self, Error> {
+        block_on(crate::Array::zeros_like(other)).map(Array)
+    }
+
+    /// See [crate::Array::ones_like].
+    pub fn ones_like(other: &Array) -> Result<Self, Error> {
+        block_on(crate::Array::ones_like(other)).map(Array)
+    }
+
+    /// See [crate::Array::arange].
+    pub fn arange<T: Element>(start: T, stop: T, step: T) -> Result<Self, Error> {
+        block_on(crate::Array::arange(start, stop, step)).map(Array)
+    }
+
+    /// See [crate::Array::linspace].
+    pub fn linspace(start: f32, stop: f32, num: usize) -> Result<Self, Error> {
+        block_on(crate::Array::linspace(start, stop, num)).map(Array)
+    }
+
+    /// See [crate::Array::eye].
+    pub fn eye<T: Element>(n: usize) -> Result<Self, Error> {
+        block_on(crate::Array::eye::<T>(n)).map(Array)
+    }
+
+    /// See [crate::Array::meshgrid].
+    pub fn meshgrid(x: &Array, y: &Array) -> Result<(Self, Self), Error> {
+        block_on(crate::Array::meshgrid(x, y)).map(|(xs, ys)| (Array(xs), Array(ys)))
+    }
+
+    /// See [crate::Array::add].
+    pub fn add(&self, other: &Array) -> Result<Array, Error> {
+        block_on(self.0.add(other)).map(Array)
+    }
+
+    /// See [crate::Array::subtract].
+    pub fn subtract(&self, other: &Array) -> Result<Array, Error> {
+        block_on(self.0.subtract(other)).map(Array)
+    }
+
+    /// See [crate::Array::multiply].
+    pub fn multiply(&self, other: &Array) -> Result<Array, Error> {
+        block_on(self.0.multiply(other)).map(Array)
+    }
+
+    /// See [crate::Array::divide].
+    pub fn divide(&self, other: &Array) -> Result<Array, Error> {
+        block_on(self.0.divide(other)).map(Array)
+    }
+
+    /// See [crate::Array::map].
+    pub fn map(&self, expression: &str) -> Result<Array, Error> {
+        block_on(self.0.map(expression)).map(Array)
+    }
+
+    /// See [crate::Array::eval].
+    pub fn eval(&self) -> Result<(), Error> {
+        block_on(self.0.eval())
+    }
+
+    /// See [crate::Array::deep_copy].
+    pub fn deep_copy(&self) -> Result<Array, Error> {
+        block_on(self.0.deep_copy()).map(Array)
+    }
+
+    /// See [crate::Array::to_vec].
+    pub fn to_vec<T: Element>(&self) -> Result<Vec<T>, Error> {
+        block_on(self.0.to_vec())
+    }
+
+    /// See [crate::Array::read_range].
+    pub fn read_range<T: Element>(&self, offset: usize, len: usize) -> Result<Vec<T>, Error> {
+        block_on(self.0.read_range(offset, len))
+    }
+
+    /// See [crate::Array::read_slice].
+    pub fn read_slice<T: Element>(&self, ranges: &[Range<usize>; 4]) -> Result<Vec<T>, Error> {
+        block_on(self.0.read_slice(ranges))
+    }
+
+    /// See [crate::Array::write].
+    pub fn write<T: Element>(&mut self, offset: usize, data: &[T]) -> Result<(), Error> {
+        block_on(self.0.write(offset, data))
+    }
+
+    /// See [crate::Array::write_slice].
+    pub fn write_slice<T: Element>(&mut self, ranges: &[Range<usize>; 4], data: &[T]) -> Result<(), Error> {
+        block_on(self.0.write_slice(ranges, data))
+    }
+
+    /// See [crate::Array::double_test].
+    pub fn double_test(&mut self) -> Result<Vec<u32>, Error> {
+        block_on(self.0.double_test())
+    }
+
+    /// The wrapped [crate::Array], for use with the async API.
+    pub fn into_inner(self) -> crate::Array {
+        self.0
+    }
+}
+
+/// See [crate::zip_map].
+pub fn zip_map(inputs: &[&Array], expression: &str) -> Result<Array, Error> {
+    let inputs = inputs.iter().map(|input| &input.0).collect::<Vec<_>>();
+
+    block_on(crate::zip_map(&inputs, expression)).map(Array)
+}
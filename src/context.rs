@@ -0,0 +1,584 @@
+use crate::adapter::AdapterSelector;
+use crate::batch::CommandBatch;
+use crate::dtype::{DType, Element};
+use crate::error::Error;
+use crate::execution::{BackendKind, Executor, Launch, Operation};
+use crate::graph::{FusedKernel, Node, Storage};
+use crate::kernel::{Grid, Kernel};
+use crate::map::{CacheStats, MapKernel, DEFAULT_MAP_CACHE_CAPACITY};
+use crate::memory::MemoryStats;
+use crate::pool::{PoolStats, DEFAULT_POOL_CAPACITY};
+use crate::staging::DEFAULT_STAGING_BUDGET;
+use crate::turns::Turns;
+use crate::utils::{self, Nested};
+use crate::Array;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use uuid::Uuid;
+use wgpu::{AdapterInfo, Backends, Features, Limits, PowerPreference};
+
+/// Process-wide [Context] used by the [Array] constructors that don't take one.
+static GLOBAL: OnceLock<Context> = OnceLock::new();
+
+/// Taken while the global context is built, so concurrent first callers wait for that build instead of each opening
+/// a device of their own.
+static GLOBAL_TURNS: OnceLock<Turns> = OnceLock::new();
+
+/// A handle to one device. [Array]s are created from a [Context] and keep it alive for as long as they exist.
+/// Cloning is cheap; clones share the same device and buffers.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() {
+/// let ctx = luma::Context::builder()
+///     .power_preference(luma::PowerPreference::LowPower)
+///     .build()
+///     .await
+///     .unwrap();
+/// let array = ctx.array(&[3, 1, 1, 1], &[1u32, 6, 5]).await.unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub(crate) executor: Arc<Executor>,
+}
+
+/// Settings for the device behind a [Context].
+#[derive(Debug, Clone)]
+pub struct ContextBuilder {
+    pub(crate) backend: BackendKind,
+    pub(crate) adapter: AdapterSelector,
+    pub(crate) force_fallback_adapter: bool,
+    pub(crate) backends: Backends,
+    pub(crate) power_preference: PowerPreference,
+    pub(crate) limits: Option<Limits>, // None asks for everything the adapter supports.
+    pub(crate) features: Features,
+    pub(crate) shader_directory: Option<PathBuf>,
+    pub(crate) pipeline_cache_directory: Option<PathBuf>,
+    pub(crate) prewarm: Vec<String>,
+    pub(crate) pool_capacity: u64,
+    pub(crate) staging_budget: u64,
+    pub(crate) memory_limit: Option<u64>,
+    pub(crate) map_cache_capacity: usize,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        ContextBuilder {
+            backend: BackendKind::Auto,
+            adapter: AdapterSelector::Default,
+            force_fallback_adapter: false,
+            backends: Backends::PRIMARY,
+            power_preference: PowerPreference::HighPerformance, // HighPerformance will tell it to return adapters that offer higher performance, like GPUs.
+            limits: None,
+            features: Features::empty(),
+            shader_directory: None,
+            pipeline_cache_directory: None,
+            prewarm: Vec::new(),
+            pool_capacity: DEFAULT_POOL_CAPACITY,
+            staging_budget: DEFAULT_STAGING_BUDGET,
+            memory_limit: None,
+            map_cache_capacity: DEFAULT_MAP_CACHE_CAPACITY,
+        }
+    }
+}
+
+impl ContextBuilder {
+    /// Whether to run on the GPU, the CPU, or the GPU when there is one. The `LUMA_BACKEND` environment variable overrides this.
+    pub fn backend(mut self, backend: BackendKind) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Which adapter to create the device on. The `LUMA_ADAPTER` environment variable overrides this.
+    pub fn adapter(mut self, adapter: AdapterSelector) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    /// Only accept the software fallback adapter when letting wgpu pick one, e.g. for headless CI.
+    pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    /// Which graphics APIs adapters may come from.
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    /// Whether to prefer a high performance or a low power adapter.
+    pub fn power_preference(mut self, power_preference: PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Limits the device must support. By default the device gets the best limits the adapter offers, so arrays
+    /// can use the largest buffers it allows; arrays larger than that are split over several buffers.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Features the device must implement.
+    pub fn features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Extra directory to load `.wgsl` kernels from, on top of the built-in ones embedded in the crate.
+    /// Each file becomes a kernel named after its stem; other files are skipped.
+    pub fn shader_directory(mut self, shader_directory: impl Into<PathBuf>) -> Self {
+        self.shader_directory = Some(shader_directory.into());
+        self
+    }
+
+    /// Directory to persist compiled pipelines in, so later runs on the same adapter and driver skip shader compilation.
+    /// Only takes effect on devices that support [Features::PIPELINE_CACHE], currently Vulkan.
+    pub fn pipeline_cache_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.pipeline_cache_directory = Some(directory.into());
+        self
+    }
+
+    /// Kernels to compile while building the context. Every other kernel is compiled the first time it runs.
+    /// Names are those of the `.wgsl` files, e.g. `"fill"` or `"tile"`.
+    pub fn prewarm<S: Into<String>>(mut self, kernels: impl IntoIterator<Item = S>) -> Self {
+        self.prewarm = kernels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Bytes of freed buffers to keep for reuse by new arrays of a similar size, 256 MiB by default.
+    /// Buffers freed once the pool is full are released right away; zero turns recycling off.
+    pub fn pool_capacity(mut self, bytes: u64) -> Self {
+        self.pool_capacity = bytes;
+        self
+    }
+
+    /// Size of the largest staging buffer reads copy through, 64 MiB by default. Staging buffers are shared by all arrays
+    /// and only created on the first read; larger arrays are read back in chunks of this size.
+    pub fn staging_budget(mut self, bytes: u64) -> Self {
+        self.staging_budget = bytes;
+        self
+    }
+
+    /// Most bytes the context may hold across every kind of buffer, see [MemoryStats::total].
+    /// An allocation that would exceed it first frees the idle buffers, then fails with [Error::OutOfMemory]
+    /// instead of leaving it to the driver. Unlimited by default.
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Number of kernels generated for map expressions to keep, 256 by default. Expressions that are used again skip
+    /// validating and lowering the WGSL; the least recently used kernel makes room for a new one. The device keeps
+    /// the shaders and pipelines of as many fused chains of maps, and of at least one.
+    pub fn map_cache_capacity(mut self, kernels: usize) -> Self {
+        self.map_cache_capacity = kernels;
+        self
+    }
+
+    /// Sets up the backend with these settings, requesting an adapter and device unless it runs on the CPU.
+    pub async fn build(self) -> Result<Context, Error> {
+        let executor = Executor::new(&self).await?;
+
+        Ok(Context {
+            executor: Arc::new(executor),
+        })
+    }
+}
+
+impl Context {
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::default()
+    }
+
+    /// Creates a [Context] with the default settings.
+    pub async fn new() -> Result<Self, Error> {
+        Context::builder().build().await
+    }
+
+    /// Returns the process-wide default [Context], setting it up first if this is the first call.
+    /// Runs on whatever executor polls it, or none at all with [crate::blocking::block_on].
+    pub async fn global() -> Result<Self, Error> {
+        if let Some(ctx) = GLOBAL.get() {
+            return Ok(ctx.clone());
+        }
+
+        let _turn = GLOBAL_TURNS.get_or_init(Turns::new).take().await;
+        // Built by the caller whose turn came first; if that build failed, this caller tries again.
+        if let Some(ctx) = GLOBAL.get() {
+            return Ok(ctx.clone());
+        }
+
+        let ctx = Context::new().await?;
+        Ok(GLOBAL.get_or_init(|| ctx).clone())
+    }
+
+    /// Information about the adapter the device was created on.
+    /// Fails with [Error::NoAdapter] on the CPU backend.
+    pub fn adapter_info(&self) -> Result<AdapterInfo, Error> {
+        self.executor.adapter_info().ok_or(Error::NoAdapter)
+    }
+
+    /// The backend operations run on. [BackendKind::Auto] has already been resolved to the one that was picked.
+    pub fn backend(&self) -> BackendKind {
+        self.executor.kind()
+    }
+
+    /// Compiles the kernels in `names` now, so their first use doesn't pay for the compilation.
+    /// Fails with [Error::ShaderCompile] for a name no shader is registered under.
+    pub async fn prewarm(&self, names: &[&str]) -> Result<(), Error> {
+        let names = names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        self.executor.prewarm(&names).await
+    }
+
+    /// Registers a custom WGSL kernel under its name, compiling it right away.
+    /// Fails with [Error::ShaderCompile] if the source doesn't compile, and with [Error::Unsupported] on the CPU backend.
+    pub async fn register_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        self.executor.register_kernel(kernel).await
+    }
+
+    /// Registers `kernel` unless one of the same name is registered already, as the launchers `#[luma::kernel]`
+    /// generates do on every call.
+    #[doc(hidden)]
+    pub async fn ensure_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        self.executor.ensure_kernel(kernel).await
+    }
+
+    /// Runs the custom kernel `name` over `grid`, binding `inputs` read-only, then `outputs`, then `params` as a uniform.
+    /// The counts have to match what the [Kernel] declared, and every array has to live on this context.
+    /// Arrays larger than the device can bind at once are split over several buffers, which custom kernels can't take;
+    /// they fail with [Error::Unsupported].
+    pub async fn run_kernel(&self, name: &str, inputs: &[&Array], outputs: &mut [&mut Array], params: &[u32], grid: Grid) -> Result<(), Error> {
+        let mut contexts = inputs.iter().map(|array| &array.context).chain(outputs.iter().map(|array| &array.context));
+        if contexts.any(|context| !self.same_device(context)) {
+            return Err(Error::InvalidArgument(format!("arrays passed to kernel {:?} live on a different context", name)));
+        }
+        let mut buffers = Vec::new();
+        for input in inputs {
+            buffers.push(input.storage().await?);
+        }
+        for output in outputs.iter_mut() {
+            buffers.push(output.storage_mut().await?);
+        }
+        let ids = buffers.iter().map(|storage| &storage.id).collect::<Vec<_>>();
+        let (inputs, outputs) = ids.split_at(inputs.len());
+
+        self.executor.run_kernel(name, inputs, outputs, params, grid).await
+    }
+
+    /// Releases the buffers the pool keeps for reuse and the idle staging buffers. Memory of live arrays is unaffected.
+    pub fn empty_cache(&self) {
+        self.executor.empty_cache();
+    }
+
+    /// How often allocations were served from the pool, and what it currently holds.
+    ///
+    /// # Example
+    /// ```
+    /// # luma::blocking::block_on(async {
+    /// let ctx = luma::Context::builder().backend(luma::BackendKind::Cpu).build().await?;
+    /// drop(ctx.zeros::<f32>(&[1024, 1, 1, 1]).await?);
+    /// let _again = ctx.zeros::<f32>(&[1000, 1, 1, 1]).await?; // Same bucket, so it reuses the freed buffer.
+    /// assert_eq!(ctx.pool_stats().hits, 1);
+    /// # Ok::<(), luma::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn pool_stats(&self) -> PoolStats {
+        self.executor.pool_stats()
+    }
+
+    /// The bytes this context holds by kind of buffer, the number of live arrays and the peak usage so far.
+    ///
+    /// # Example
+    /// ```
+    /// # luma::blocking::block_on(async {
+    /// let ctx = luma::Context::builder()
+    ///     .backend(luma::BackendKind::Cpu)
+    ///     .memory_limit(1 << 20)
+    ///     .build()
+    ///     .await?;
+    /// let a = ctx.zeros::<f32>(&[1024, 1, 1, 1]).await?;
+    /// assert_eq!(ctx.memory_stats().storage, 4096);
+    /// assert_eq!(ctx.memory_stats().arrays, 1);
+    /// assert!(matches!(ctx.zeros::<f32>(&[1 << 20, 1, 1, 1]).await, Err(luma::Error::OutOfMemory(_))));
+    /// # Ok::<(), luma::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.executor.memory_stats()
+    }
+
+    /// How many kernels this context keeps for map expressions, and the shaders and pipelines the device keeps.
+    /// Only the GPU backend compiles shaders and pipelines; on the CPU those counts stay at zero.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.executor.cache_stats()
+    }
+
+    /// Starts a [CommandBatch], which holds back the work done on this context until it's submitted.
+    pub fn batch(&self) -> CommandBatch {
+        CommandBatch::new(self)
+    }
+
+    /// Whether both handles refer to the same device.
+    pub fn same_device(&self, other: &Context) -> bool {
+        Arc::ptr_eq(&self.executor, &other.executor)
+    }
+
+    /// Creates an [Array] of the given dimensions from flat, row-major data.
+    /// The product of `dimensions` must equal `data.len()`; any zero dimension makes an empty array.
+    pub async fn array<T>(&self, dimensions: &[usize; 4], data: &[T]) -> Result<Array, Error>
+    where
+        T: Element,
+    {
+        utils::check_shape(dimensions, data.len())?;
+
+        let id: String = Uuid::new_v4().into();
+        // Setup input output buffers with our data
+        self.executor.setup_buffers(dimensions, data, id.clone()).await?;
+        let storage = Arc::new(Storage::new(&self.executor, id));
+
+        Ok(self.wrap(dimensions, T::DTYPE, Node::Buffer(storage)))
+    }
+
+    /// Creates an [Array] from nested data, inferring the dimensions from the nesting.
+    /// Ragged data, where rows of the same level differ in length, is rejected.
+    pub async fn from_nested<N: Nested>(&self, data: N) -> Result<Array, Error> {
+        let (dimensions, flat) = utils::flatten(data)?;
+
+        self.array(&dimensions, &flat).await
+    }
+
+    /// Creates an [Array] filled with zeros.
+    pub async fn zeros<T: Element>(&self, dimensions: &[usize; 4]) -> Result<Array, Error> {
+        self.zeros_of(dimensions, T::DTYPE).await
+    }
+
+    /// Creates an [Array] filled with ones.
+    pub async fn ones<T: Element>(&self, dimensions: &[usize; 4]) -> Result<Array, Error> {
+        self.full(dimensions, T::ONE).await
+    }
+
+    /// Creates an [Array] with every element set to `value`.
+    pub async fn full<T: Element>(&self, dimensions: &[usize; 4], value: T) -> Result<Array, Error> {
+        self.full_of(dimensions, T::DTYPE, value.to_bits()).await
+    }
+
+    /// Creates a one dimensional [Array] holding `start, start + step, ...` up to but excluding `stop`.
+    pub async fn arange<T: Element>(&self, start: T, stop: T, step: T) -> Result<Array, Error> {
+        if step.to_f64() == 0.0 {
+            return Err(Error::InvalidArgument("arange step must not be zero".into()));
+        }
+        let len = ((stop.to_f64() - start.to_f64()) / step.to_f64()).ceil().max(0.0) as usize;
+
+        self.ramp(len, T::DTYPE, start.to_bits(), step.to_bits()).await
+    }
+
+    /// Creates a one dimensional [Array] of `num` evenly spaced values from `start` to `stop`, inclusive.
+    pub async fn linspace(&self, start: f32, stop: f32, num: usize) -> Result<Array, Error> {
+        let step = if num > 1 { (stop - start) / (num - 1) as f32 } else { 0.0 };
+
+        self.ramp(num, DType::F32, start.to_bits(), step.to_bits()).await
+    }
+
+    /// Creates an `n` by `n` identity matrix.
+    pub async fn eye<T: Element>(&self, n: usize) -> Result<Array, Error> {
+        let dimensions = [n, n, 1, 1];
+        let len = n.saturating_mul(n);
+        let params = [word(n)?, word(len)?, T::ONE.to_bits(), 0];
+        let storage = self.allocate(&dimensions, T::DTYPE).await?;
+        if len > 0 {
+            self.executor.launch(&Launch {
+                operation: Operation::Eye,
+                output: &storage.id,
+                inputs: &[],
+                params,
+                len,
+            }).await?;
+        }
+
+        Ok(self.wrap(&dimensions, T::DTYPE, Node::Buffer(storage)))
+    }
+}
+
+// Constructor helpers
+impl Context {
+    /// Allocates buffers for an array whose contents are never uploaded from the host.
+    /// They hold all zero bits until a kernel writes to them: new buffers are zero initialized, and buffers recycled
+    /// from the pool are cleared before they're reused.
+    pub(crate) async fn allocate(&self, dimensions: &[usize; 4], dtype: DType) -> Result<Arc<Storage>, Error> {
+        let id: String = Uuid::new_v4().into();
+        let size = (dimensions.iter().product::<usize>() * dtype.size()) as u64;
+        self.executor.allocate_buffers(dimensions, size, id.clone()).await?;
+
+        Ok(Arc::new(Storage::new(&self.executor, id)))
+    }
+
+    /// Wraps `node` in an [Array] on this context. Arrays created with their buffers share the buffers' id.
+    pub(crate) fn wrap(&self, dimensions: &[usize; 4], dtype: DType, node: Node) -> Array {
+        let id = match &node {
+            Node::Buffer(storage) => storage.id.clone(),
+            Node::Map(_) => Uuid::new_v4().into(),
+        };
+
+        Array {
+            context: self.clone(),
+            dimensions: *dimensions,
+            dtype,
+            id,
+            node: Mutex::new(node),
+        }
+    }
+
+    pub(crate) async fn zeros_of(&self, dimensions: &[usize; 4], dtype: DType) -> Result<Array, Error> {
+        let storage = self.allocate(dimensions, dtype).await?;
+        if dimensions.iter().product::<usize>() > 0 {
+            self.executor.clear(&storage.id).await?;
+        }
+
+        Ok(self.wrap(dimensions, dtype, Node::Buffer(storage)))
+    }
+
+    pub(crate) async fn full_of(&self, dimensions: &[usize; 4], dtype: DType, bits: u32) -> Result<Array, Error> {
+        if bits == 0 {
+            return self.zeros_of(dimensions, dtype).await;
+        }
+        let len = dimensions.iter().product::<usize>();
+        let params = [bits, word(len)?, 0, 0];
+        let storage = self.allocate(dimensions, dtype).await?;
+        if len > 0 {
+            self.executor.launch(&Launch {
+                operation: Operation::Fill,
+                output: &storage.id,
+                inputs: &[],
+                params,
+                len,
+            }).await?;
+        }
+
+        Ok(self.wrap(dimensions, dtype, Node::Buffer(storage)))
+    }
+
+    async fn ramp(&self, len: usize, dtype: DType, start: u32, step: u32) -> Result<Array, Error> {
+        let dimensions = [len, 1, 1, 1];
+        let params = [start, step, word(len)?, dtype as u32];
+        let storage = self.allocate(&dimensions, dtype).await?;
+        if len > 0 {
+            self.executor.launch(&Launch {
+                operation: Operation::Ramp,
+                output: &storage.id,
+                inputs: &[],
+                params,
+                len,
+            }).await?;
+        }
+
+        Ok(self.wrap(&dimensions, dtype, Node::Buffer(storage)))
+    }
+
+    /// Creates an array of `dimensions` where element `i` is `source[(i / div) % modulus]`.
+    pub(crate) async fn tile(&self, source: &Array, dimensions: &[usize; 4], div: usize, modulus: usize) -> Result<Array, Error> {
+        let len = dimensions.iter().product::<usize>();
+        let params = [word(len)?, word(div)?, word(modulus)?, 0];
+        let storage = self.allocate(dimensions, source.dtype).await?;
+        if len > 0 {
+            let source = source.storage().await?;
+            self.executor.launch(&Launch {
+                operation: Operation::Tile,
+                output: &storage.id,
+                inputs: &[&source.id],
+                params,
+                len,
+            }).await?;
+        }
+
+        Ok(self.wrap(dimensions, source.dtype, Node::Buffer(storage)))
+    }
+
+    /// Creates an array holding `expression` of `a` and `b`, bound to the elements of `lhs` and `rhs`, which must match
+    /// in dtype and shape. Like a map, it only runs once the result is needed.
+    pub(crate) async fn binary(&self, expression: &str, lhs: &Array, rhs: &Array) -> Result<Array, Error> {
+        if lhs.dtype != rhs.dtype {
+            return Err(Error::DtypeMismatch { expected: lhs.dtype, actual: rhs.dtype });
+        }
+        if lhs.dimensions != rhs.dimensions {
+            return Err(Error::ShapeMismatch {
+                dimensions: lhs.dimensions,
+                expected: lhs.len(),
+                actual: rhs.len(),
+            });
+        }
+        if !lhs.context.same_device(&rhs.context) {
+            return Err(Error::InvalidArgument("operands live on different contexts".into()));
+        }
+        let kernel = self.executor.maps.get(lhs.dtype, &["a", "b"], expression)?;
+
+        self.pending(kernel, &[lhs, rhs]).await
+    }
+
+    /// Creates an array holding `expression` evaluated for every element of `inputs`, whose elements are bound to `arguments`.
+    /// The inputs must match in dtype and dimensions; the result has the same. Nothing runs until the result is needed.
+    pub(crate) async fn map(&self, inputs: &[&Array], arguments: &[&str], expression: &str) -> Result<Array, Error> {
+        let Some(first) = inputs.first() else {
+            return Err(Error::InvalidArgument("map needs at least one input".into()));
+        };
+        for input in &inputs[1..] {
+            if input.dtype != first.dtype {
+                return Err(Error::DtypeMismatch { expected: first.dtype, actual: input.dtype });
+            }
+            if input.dimensions != first.dimensions {
+                return Err(Error::ShapeMismatch {
+                    dimensions: first.dimensions,
+                    expected: first.len(),
+                    actual: input.len(),
+                });
+            }
+        }
+        if inputs.iter().any(|input| !self.same_device(&input.context)) {
+            return Err(Error::InvalidArgument("map inputs live on different contexts".into()));
+        }
+
+        let kernel = self.executor.maps.get(first.dtype, arguments, expression)?;
+
+        self.pending(kernel, inputs).await
+    }
+
+    /// Creates an array that evaluates `kernel` over `inputs` once it's needed. Pending expressions of the inputs are
+    /// fused into it, unless the fused kernel would grow too large; then the inputs are evaluated first.
+    async fn pending(&self, kernel: Arc<MapKernel>, inputs: &[&Array]) -> Result<Array, Error> {
+        let mut node = Node::map(kernel.clone(), inputs.iter().map(|input| input.node()).collect());
+        if !node.fusible() {
+            let mut operands = Vec::new();
+            for input in inputs {
+                operands.push(Node::Buffer(input.storage().await?));
+            }
+            node = Node::map(kernel, operands);
+        }
+
+        Ok(self.wrap(&inputs[0].dimensions, inputs[0].dtype, node))
+    }
+
+    /// Runs the pending expression `node` as a single fused kernel, returning the buffers holding the result.
+    pub(crate) async fn materialize(&self, node: &Node, dimensions: &[usize; 4], dtype: DType) -> Result<Arc<Storage>, Error> {
+        let storage = self.allocate(dimensions, dtype).await?;
+        let len = dimensions.iter().product::<usize>();
+        if len == 0 {
+            return Ok(storage);
+        }
+
+        let (kernel, inputs) = FusedKernel::new(dtype, node);
+        let ids = inputs.iter().map(|input| &input.id).collect::<Vec<_>>();
+        self.executor.run_fused(&kernel, &storage.id, &ids, len).await?;
+
+        Ok(storage)
+    }
+}
+
+/// Narrows a length or index to the 32 bit word the constructor kernels take it as.
+fn word(value: usize) -> Result<u32, Error> {
+    u32::try_from(value).map_err(|_| {
+        Error::InvalidArgument(format!("constructor kernels handle at most {} elements, but {} were given", u32::MAX, value))
+    })
+}
@@ -0,0 +1,308 @@
+use crate::dtype::DType;
+use crate::error::Error;
+use crate::execution::{Launch, Operation};
+use crate::graph::FusedKernel;
+use crate::map::{Value, MAX_MAP_INPUTS};
+use crate::memory::{Allocation, Category, MemoryStats, MemoryTracker};
+use crate::pool::{self, BufferPool, PoolStats};
+use bytemuck::Pod;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+/// Below this many elements a kernel runs on the calling thread; spawning workers would cost more than it saves.
+const MIN_ELEMENTS_PER_THREAD: usize = 1 << 14;
+
+/// Host side stand-in for the device buffers of one array.
+/// Every element type is 32 bits wide, so the data is kept as raw words just like the shaders see it.
+/// Operations hold on to the data of their inputs while they run, so they don't need to keep the map locked.
+#[derive(Debug)]
+struct CpuBuffer {
+    dimensions: [usize; 4],
+    data: Arc<Vec<u32>>,
+    allocation: Allocation, // Of the words the bucket of `data` has room for.
+}
+
+/// CPU backend of the [crate::execution::Executor]. Runs every [Operation] with plain Rust on worker threads,
+/// for machines without a GPU and as a reference to test the shaders against.
+#[derive(Debug)]
+pub struct CpuBackend {
+    buffers: RwLock<HashMap<String, CpuBuffer>>,
+    pool: BufferPool<(Vec<u32>, Allocation)>, // Emptied vectors, with room for the words of their bucket.
+    memory: Arc<MemoryTracker>,
+}
+
+impl CpuBackend {
+    /// Creates a backend whose pool keeps at most `pool_capacity` bytes of freed buffers,
+    /// and whose buffers may take up to `memory_limit` bytes.
+    pub fn new(pool_capacity: u64, memory_limit: Option<u64>) -> Self {
+        CpuBackend {
+            buffers: RwLock::new(HashMap::new()),
+            pool: BufferPool::new(pool_capacity),
+            memory: MemoryTracker::new(memory_limit),
+        }
+    }
+
+    /// Frees the buffer of the array, handing it to the pool for reuse.
+    pub fn drop(&self, id: &String) {
+        let Some(mut buffer) = self.buffers.write().unwrap().remove(id) else {
+            return;
+        };
+        // Still being read by an operation, which frees it once it's done.
+        let Ok(mut data) = Arc::try_unwrap(buffer.data) else {
+            return;
+        };
+        let bucket = pool::bucket(data.len() as u64 * 4);
+        data.clear();
+        buffer.allocation.move_to(Category::Pool);
+        self.pool.give(bucket, (data, buffer.allocation));
+    }
+
+    /// Drops every idle buffer the pool holds.
+    pub fn empty_cache(&self) {
+        self.pool.clear();
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            arrays: self.buffers.read().unwrap().len(),
+            ..self.memory.stats()
+        }
+    }
+
+    /// Copies `data` into a new buffer registered under `id`.
+    pub fn setup_buffers<T: Pod>(&self, dimensions: &[usize; 4], data: &[T], id: String) -> Result<(), Error> {
+        let words = bytemuck::cast_slice::<T, u32>(data);
+        let (mut data, allocation) = self.acquire(words.len())?;
+        data.extend_from_slice(words);
+        let data = Arc::new(data);
+        self.buffers.write().unwrap().insert(id, CpuBuffer { dimensions: *dimensions, data, allocation });
+
+        Ok(())
+    }
+
+    /// Registers a zeroed buffer of `size` bytes under `id`.
+    pub fn allocate_buffers(&self, dimensions: &[usize; 4], size: u64, id: String) -> Result<(), Error> {
+        let len = size as usize / std::mem::size_of::<u32>();
+        let (mut data, allocation) = self.acquire(len)?;
+        data.resize(len, 0);
+        let data = Arc::new(data);
+        self.buffers.write().unwrap().insert(id, CpuBuffer { dimensions: *dimensions, data, allocation });
+
+        Ok(())
+    }
+
+    /// Sets every element of the array to zero bits.
+    pub fn clear(&self, id: &String) -> Result<(), Error> {
+        let mut buffers = self.buffers.write().unwrap();
+        let buffer = buffers.get_mut(id).ok_or_else(|| Error::UnknownArray(id.clone()))?;
+        Arc::make_mut(&mut buffer.data).fill(0);
+
+        Ok(())
+    }
+
+    /// Copies the elements of `source` into `destination`.
+    pub fn copy(&self, source: &String, destination: &String) -> Result<(), Error> {
+        let mut buffers = self.buffers.write().unwrap();
+        let data = buffers.get(source).ok_or_else(|| Error::UnknownArray(source.clone()))?.data.clone();
+        let destination = buffers.get_mut(destination).ok_or_else(|| Error::UnknownArray(destination.clone()))?;
+        let destination = Arc::make_mut(&mut destination.data);
+        destination.clear();
+        destination.extend_from_slice(&data);
+
+        Ok(())
+    }
+
+    /// Runs a built-in [Operation], reading the same parameter words as the matching shader.
+    pub fn launch(&self, launch: &Launch<'_>) -> Result<(), Error> {
+        self.compute(launch.output, launch.inputs, |inputs, output| run(launch, inputs, &mut output[..launch.len]))
+    }
+
+    /// Evaluates a fused map expression for the first `len` elements.
+    pub fn run_fused(&self, kernel: &FusedKernel, output: &String, inputs: &[&String], len: usize) -> Result<(), Error> {
+        let programs = kernel.programs()?;
+        self.compute(output, inputs, |inputs, output| {
+            par_map(&mut output[..len], |i| {
+                let mut elements = [Value::U32(0); MAX_MAP_INPUTS];
+                for (element, input) in elements.iter_mut().zip(inputs) {
+                    *element = Value::from_bits(kernel.dtype, input[i]);
+                }
+                kernel.eval(&programs, &elements[..inputs.len()]).to_bits()
+            });
+            Ok(())
+        })
+    }
+
+    /// Hands `f` the data of `inputs` next to the data of `output` to write to.
+    /// The map is only locked to look the buffers up and to put the output back, not while `f` runs.
+    /// Nothing else uses the output meanwhile: outputs are either new arrays or held exclusively by the caller.
+    fn compute(
+        &self,
+        output: &String,
+        inputs: &[&String],
+        f: impl FnOnce(&[&[u32]], &mut Vec<u32>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let (inputs, mut data) = {
+            let mut buffers = self.buffers.write().unwrap();
+            let inputs = inputs
+                .iter()
+                .map(|&id| buffers.get(id).map(|buffer| buffer.data.clone()).ok_or_else(|| Error::UnknownArray(id.clone())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let buffer = buffers.get_mut(output).ok_or_else(|| Error::UnknownArray(output.clone()))?;
+            (inputs, std::mem::take(&mut buffer.data))
+        };
+
+        // An input that is also the output still holds the old data, so writing to the output copies it first.
+        let slices = inputs.iter().map(|data| data.as_slice()).collect::<Vec<_>>();
+        let result = f(&slices, Arc::make_mut(&mut data));
+        if let Some(buffer) = self.buffers.write().unwrap().get_mut(output) {
+            buffer.data = data;
+        }
+
+        result
+    }
+
+    /// Takes an empty vector with room for `len` words from the pool, or allocates one.
+    /// If a new one would exceed the memory limit, the pool is emptied to make room before giving up.
+    fn acquire(&self, len: usize) -> Result<(Vec<u32>, Allocation), Error> {
+        let bucket = pool::bucket(len as u64 * 4);
+        if let Some((data, mut allocation)) = self.pool.take(bucket) {
+            allocation.move_to(Category::Storage);
+            return Ok((data, allocation));
+        }
+
+        let allocation = self.memory.reserve(Category::Storage, bucket).or_else(|_| {
+            self.pool.clear();
+            self.memory.reserve(Category::Storage, bucket)
+        })?;
+
+        Ok((Vec::with_capacity(bucket as usize / 4), allocation))
+    }
+
+    /// Copies the contents of the array back to the caller.
+    pub fn read<T: Pod>(&self, id: &String) -> Result<Vec<T>, Error> {
+        let buffers = self.buffers.read().unwrap();
+        let buffer = buffers.get(id).ok_or_else(|| Error::UnknownArray(id.clone()))?;
+
+        Ok(bytemuck::cast_slice::<u32, T>(&buffer.data).to_vec())
+    }
+
+    /// Copies the elements in `runs` back to the caller, one run after the other.
+    pub fn read_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>]) -> Result<Vec<T>, Error> {
+        let buffers = self.buffers.read().unwrap();
+        let buffer = buffers.get(id).ok_or_else(|| Error::UnknownArray(id.clone()))?;
+        let words = runs.iter().flat_map(|run| &buffer.data[run.clone()]).copied().collect::<Vec<_>>();
+
+        Ok(bytemuck::cast_slice::<u32, T>(&words).to_vec())
+    }
+
+    /// Overwrites the elements in `runs` with `data`, which holds the new contents of one run after the other.
+    pub fn write_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>], data: &[T]) -> Result<(), Error> {
+        let mut buffers = self.buffers.write().unwrap();
+        let buffer = buffers.get_mut(id).ok_or_else(|| Error::UnknownArray(id.clone()))?;
+        let buffer = Arc::make_mut(&mut buffer.data);
+        let mut words = bytemuck::cast_slice::<T, u32>(data);
+        for run in runs {
+            let (head, tail) = words.split_at(run.len());
+            buffer[run.clone()].copy_from_slice(head);
+            words = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Test function.
+    /// Doubles the array input in place, the same way `double.wgsl` does.
+    pub fn execute_op(&self, id: &String, operation: Operation) -> Result<Vec<u32>, Error> {
+        let len = self.buffers.read().unwrap().get(id).ok_or_else(|| Error::UnknownArray(id.clone()))?.data.len();
+        self.launch(&Launch {
+            operation,
+            output: id,
+            inputs: &[],
+            params: [len as u32, 0, 0, 0],
+            len,
+        })?;
+
+        self.read(id)
+    }
+}
+
+/// Computes every element of `output` for `launch`.
+fn run(launch: &Launch<'_>, inputs: &[&[u32]], output: &mut [u32]) -> Result<(), Error> {
+    let params = launch.params;
+    match launch.operation {
+        Operation::Double => {
+            let input = output.to_vec();
+            par_map(output, |i| input[i].wrapping_mul(2));
+        }
+        Operation::Fill => par_map(output, |_| params[0]),
+        Operation::Ramp => {
+            let [start, step, _, dtype] = params;
+            match dtype_of(dtype)? {
+                DType::F32 => par_map(output, |i| {
+                    (f32::from_bits(start) + i as f32 * f32::from_bits(step)).to_bits()
+                }),
+                DType::U32 => par_map(output, |i| start.wrapping_add((i as u32).wrapping_mul(step))),
+                DType::I32 => par_map(output, |i| {
+                    (start as i32).wrapping_add((i as i32).wrapping_mul(step as i32)) as u32
+                }),
+            }
+        }
+        Operation::Eye => {
+            let [n, _, one, _] = params;
+            let n = n as usize;
+            par_map(output, |i| if i / n == i % n { one } else { 0 });
+        }
+        Operation::Tile => {
+            let [source] = expect_inputs::<1>(launch, inputs)?;
+            let [_, div, modulus, _] = params;
+            par_map(output, |i| source[(i / div as usize) % modulus as usize]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits `output` across the available cores and sets every element to `f(index)`.
+fn par_map(output: &mut [u32], f: impl Fn(usize) -> u32 + Sync) {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_len = output.len().div_ceil(threads).max(MIN_ELEMENTS_PER_THREAD);
+    if chunk_len >= output.len() {
+        output.iter_mut().enumerate().for_each(|(i, o)| *o = f(i));
+        return;
+    }
+
+    let f = &f;
+    std::thread::scope(|scope| {
+        for (c, chunk) in output.chunks_mut(chunk_len).enumerate() {
+            scope.spawn(move || {
+                let offset = c * chunk_len;
+                chunk.iter_mut().enumerate().for_each(|(i, o)| *o = f(offset + i));
+            });
+        }
+    });
+}
+
+fn expect_inputs<'a, const N: usize>(launch: &Launch<'_>, inputs: &[&'a [u32]]) -> Result<[&'a [u32]; N], Error> {
+    inputs.try_into().map_err(|_| {
+        Error::InvalidArgument(format!(
+            "{:?} takes {} inputs, but {} were given",
+            launch.operation,
+            N,
+            inputs.len()
+        ))
+    })
+}
+
+fn dtype_of(code: u32) -> Result<DType, Error> {
+    match code {
+        0 => Ok(DType::F32),
+        1 => Ok(DType::U32),
+        2 => Ok(DType::I32),
+        _ => Err(Error::InvalidArgument(format!("unknown dtype code {}", code))),
+    }
+}
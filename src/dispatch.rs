@@ -0,0 +1,54 @@
+use crate::error::Error;
+use wgpu::Limits;
+
+/// Workgroup size for small arrays, where fewer, larger workgroups would leave most of the device idle.
+pub const SMALL_WORKGROUP_SIZE: u32 = 64;
+/// Workgroup size once there are enough elements to keep every core busy with it.
+pub const LARGE_WORKGROUP_SIZE: u32 = 256;
+/// Arrays with at least this many elements run with [LARGE_WORKGROUP_SIZE].
+const LARGE_ARRAY_LEN: usize = 1 << 16;
+
+/// How a kernel over `len` elements is launched: the size of each workgroup and how many run along x, y and z.
+/// Kernels flatten the grid back into an element index and skip everything at or past the true length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchPlan {
+    pub workgroup_size: Option<u32>, // Value for the kernel's `WORKGROUP_SIZE` override; [None] for kernels that fix their own.
+    pub workgroups: [u32; 3],
+}
+
+impl DispatchPlan {
+    /// Plans a launch over `len` elements within the device's `limits`.
+    /// Workgroups fill x first, then y, then z, each up to `max_compute_workgroups_per_dimension`.
+    pub fn new(len: usize, limits: &Limits) -> Result<Self, Error> {
+        if len > u32::MAX as usize {
+            return Err(Error::InvalidArgument(format!(
+                "a single dispatch can cover at most {} elements, but {} were given",
+                u32::MAX,
+                len
+            )));
+        }
+
+        let preferred = if len >= LARGE_ARRAY_LEN { LARGE_WORKGROUP_SIZE } else { SMALL_WORKGROUP_SIZE };
+        let workgroup_size = preferred
+            .min(limits.max_compute_workgroup_size_x)
+            .min(limits.max_compute_invocations_per_workgroup)
+            .max(1);
+
+        let max = limits.max_compute_workgroups_per_dimension.max(1) as usize;
+        let count = len.div_ceil(workgroup_size as usize).max(1);
+        let x = count.min(max);
+        let y = count.div_ceil(x).min(max);
+        let z = count.div_ceil(x * y);
+        if z > max {
+            return Err(Error::InvalidArgument(format!(
+                "{} elements need {} workgroups, more than the device can launch at once",
+                len, count
+            )));
+        }
+
+        Ok(DispatchPlan {
+            workgroup_size: Some(workgroup_size),
+            workgroups: [x as u32, y as u32, z as u32],
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use bytemuck::Pod;
+
+/// Element types an [crate::Array] can hold on the device.
+/// The discriminant is passed to kernels that need to know how to interpret the raw words.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DType {
+    F32 = 0,
+    U32 = 1,
+    I32 = 2,
+}
+
+impl DType {
+    /// Size of one element in bytes.
+    pub fn size(&self) -> usize {
+        4
+    }
+
+    /// Name of the matching WGSL scalar type.
+    pub fn wgsl_name(&self) -> &'static str {
+        match self {
+            DType::F32 => "f32",
+            DType::U32 => "u32",
+            DType::I32 => "i32",
+        }
+    }
+
+    /// Raw bit pattern of the value one.
+    pub fn one_bits(&self) -> u32 {
+        match self {
+            DType::F32 => f32::ONE.to_bits(),
+            DType::U32 => u32::ONE.to_bits(),
+            DType::I32 => i32::ONE.to_bits(),
+        }
+    }
+}
+
+mod sealed {
+    /// Keeps [super::Element] to the types below: the backends treat every element as one 32 bit word.
+    pub trait Sealed {}
+}
+
+/// Host types that can be uploaded into an [crate::Array]: `f32`, `u32` and `i32`. It can't be implemented
+/// for other types.
+///
+/// ```compile_fail
+/// #[derive(Clone, Copy, Debug)]
+/// #[repr(transparent)]
+/// struct Word(u32);
+/// unsafe impl bytemuck::Zeroable for Word {}
+/// unsafe impl bytemuck::Pod for Word {}
+///
+/// impl luma::Element for Word {
+///     const DTYPE: luma::DType = luma::DType::U32;
+///     const ZERO: Self = Word(0);
+///     const ONE: Self = Word(1);
+///
+///     fn to_bits(self) -> u32 {
+///         self.0
+///     }
+///
+///     fn to_f64(self) -> f64 {
+///         self.0 as f64
+///     }
+/// }
+/// ```
+pub trait Element: Pod + std::fmt::Debug + sealed::Sealed {
+    const DTYPE: DType;
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// Raw 32 bit pattern of the value, as the kernels see it.
+    fn to_bits(self) -> u32;
+
+    /// Lossy conversion used for host side shape computations (e.g. the length of an `arange`).
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_element {
+    ($t:ty, $dtype:expr, $zero:expr, $one:expr, $bits:expr) => {
+        impl sealed::Sealed for $t {}
+
+        impl Element for $t {
+            const DTYPE: DType = $dtype;
+            const ZERO: Self = $zero;
+            const ONE: Self = $one;
+
+            fn to_bits(self) -> u32 {
+                $bits(self)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+impl_element!(f32, DType::F32, 0.0, 1.0, f32::to_bits);
+impl_element!(u32, DType::U32, 0, 1, |v: u32| v);
+impl_element!(i32, DType::I32, 0, 1, |v: i32| v as u32);
@@ -0,0 +1,70 @@
+use crate::dtype::DType;
+use std::fmt;
+
+/// Errors returned by Luma.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// No adapter matching the request could be found.
+    NoAdapter,
+    /// The adapter refused to hand out a device.
+    DeviceRequest(String),
+    /// A shader couldn't be loaded or failed to compile.
+    ShaderCompile {
+        shader: String,
+        message: String,
+    },
+    /// The number of elements handed over doesn't match the product of the requested dimensions.
+    ShapeMismatch {
+        dimensions: [usize; 4],
+        expected: usize,
+        actual: usize,
+    },
+    /// An [crate::Array] holds a different [DType] than the one asked for.
+    DtypeMismatch {
+        expected: DType,
+        actual: DType,
+    },
+    /// The device ran out of memory.
+    OutOfMemory(String),
+    /// A buffer couldn't be mapped for reading.
+    MapFailed(String),
+    /// The device was lost; nothing else can run on it.
+    DeviceLost(String),
+    /// No buffers are registered under this array id.
+    UnknownArray(String),
+    /// wgpu rejected a call as invalid.
+    Validation(String),
+    /// An argument was outside of what the call accepts.
+    InvalidArgument(String),
+    /// The backend in use can't do this, e.g. run WGSL on the CPU.
+    Unsupported(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoAdapter => f.write_str("found no adapters"),
+            Error::DeviceRequest(message) => write!(f, "error requesting device: {}", message),
+            Error::ShaderCompile { shader, message } => {
+                write!(f, "could not compile shader {}: {}", shader, message)
+            }
+            Error::ShapeMismatch { dimensions, expected, actual } => write!(
+                f,
+                "dimensions {:?} hold {} elements, but {} were given",
+                dimensions, expected, actual
+            ),
+            Error::DtypeMismatch { expected, actual } => {
+                write!(f, "expected an array of {:?}, but it holds {:?}", expected, actual)
+            }
+            Error::OutOfMemory(message) => write!(f, "out of device memory: {}", message),
+            Error::MapFailed(message) => write!(f, "failed to map buffer: {}", message),
+            Error::DeviceLost(message) => write!(f, "device lost: {}", message),
+            Error::UnknownArray(id) => write!(f, "no buffers for array {}", id),
+            Error::Validation(message) => write!(f, "validation error: {}", message),
+            Error::InvalidArgument(message) => f.write_str(message),
+            Error::Unsupported(message) => write!(f, "unsupported on this backend: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
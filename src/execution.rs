@@ -1,416 +1,332 @@
-#![allow(dead_code)]
-use bytemuck;
+use crate::context::ContextBuilder;
+use crate::cpu::CpuBackend;
+use crate::error::Error;
+use crate::gpu::GpuBackend;
+use crate::kernel::{Grid, Kernel};
+use crate::graph::FusedKernel;
+use crate::map::{CacheStats, MapCache};
+use crate::memory::MemoryStats;
+use crate::pool::PoolStats;
 use bytemuck::Pod;
-use flume;
-use log::debug;
-use std::borrow::Cow;
-use std::collections::HashMap;
-use std::ops::Index;
-use std::sync::{Arc, RwLock};
-use wgpu::util::DeviceExt;
-use wgpu::{Buffer, Device, Features, InstanceDescriptor, InstanceFlags, MemoryHints, PowerPreference, Queue, ShaderModule};
-
-pub type ShaderResources = HashMap<String, ShaderModule>;
-
-fn decode_operation<'a>(op: Operation) -> &'a str {
+use log::{debug, warn};
+use std::ops::Range;
+use std::str::FromStr;
+use wgpu::AdapterInfo;
+
+/// Environment variable that overrides the backend picked by every [ContextBuilder].
+/// Parsed with [BackendKind::from_str].
+pub const BACKEND_ENV: &str = "LUMA_BACKEND";
+
+pub(crate) fn decode_operation<'a>(op: Operation) -> &'a str {
     match op {
-        Operation::DOUBLE => "double",
-        Operation::ADD => "add",
-        Operation::SUBTRACT => "subtract",
-        Operation::MULTIPLY => "multiply",
-        Operation::DIVIDE => "divide",
+        Operation::Double => "double",
+        Operation::Fill => "fill",
+        Operation::Ramp => "ramp",
+        Operation::Eye => "eye",
+        Operation::Tile => "tile",
     }
 }
 
-/// GpuHandle
-/// This will hold our [Device] and [Queue] for later executions
-#[derive(Debug)]
-pub struct GpuHandle {
-    pub device: Box<Device>,
-    pub queue: Box<Queue>,
+/// Operations to be performed on the given data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Double, // Still a test operation
+    Fill, // Writes a single value to every element
+    Ramp, // Writes `start + i * step` to every element
+    Eye,  // Writes an identity matrix
+    Tile, // Repeats the elements of a source buffer
 }
 
+/// One run of a built-in [Operation], described independently of the backend that executes it.
+/// `params` are the four words of the kernel's uniform block; `len` is the number of output elements to compute.
 #[derive(Debug)]
-pub struct Buffers {
-    storage_buffer: Buffer,
-    staging_buffer: Buffer,
-    dimensions_buffer: Buffer,
+pub struct Launch<'a> {
+    pub operation: Operation,
+    pub output: &'a String,
+    pub inputs: &'a [&'a String],
+    pub params: [u32; 4],
+    pub len: usize,
 }
 
-/// Operations to be performed on the given data.
-pub enum Operation {
-    DOUBLE, // Still a test operation
-    ADD,
-    SUBTRACT,
-    MULTIPLY,
-    DIVIDE,
+/// Which kind of device a [crate::Context] runs its operations on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// The GPU if an adapter can be found, otherwise the CPU.
+    #[default]
+    Auto,
+    /// Only a GPU adapter; fails with [Error::NoAdapter] if there is none.
+    Gpu,
+    /// Plain Rust on the host's cores. Needs no adapter at all.
+    Cpu,
 }
 
-impl GpuHandle {
-    pub fn new(device: Device, queue: Queue) -> Self {
-        GpuHandle {
-            device: Box::new(device),
-            queue: Box::new(queue),
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    /// Parses the format of [BACKEND_ENV]: `auto`, `gpu` or `cpu`.
+    ///
+    /// # Example
+    /// ```
+    /// use luma::BackendKind;
+    ///
+    /// assert_eq!("cpu".parse::<BackendKind>().unwrap(), BackendKind::Cpu);
+    /// assert!("tpu".parse::<BackendKind>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(BackendKind::Auto),
+            "gpu" => Ok(BackendKind::Gpu),
+            "cpu" => Ok(BackendKind::Cpu),
+            _ => Err(Error::InvalidArgument(format!("unknown backend {:?}, expected auto, gpu or cpu", s))),
         }
     }
 }
 
-/// Executor object. Holds [ShaderResources], [GpuHandle] and [Buffer]s for dynamically executing commands on the GPU
-/// Shouldn't be called by the user. A static [Executor] must exist for the [Array] to execute operations.
 #[derive(Debug)]
-pub struct Executor {
-    pub adapter: Option<Box<GpuHandle>>,
-    pub shaders: Option<Box<ShaderResources>>,
-    buffers: Arc<RwLock<HashMap<String, Buffers>>>,
+enum Backend {
+    Gpu(GpuBackend),
+    Cpu(CpuBackend),
 }
 
-impl Default for Executor {
-    fn default() -> Self {
-        Executor {
-            adapter: None,
-            shaders: None,
-            buffers: Arc::new(RwLock::new(HashMap::new())), // RwLock locks the value so that there can only be one writer at a time. Also, can be used for interior mutability.
-        }
-    }
+/// Executor
+/// Owns the backend a [crate::Context] runs on and forwards every buffer and kernel call to it.
+#[derive(Debug)]
+pub struct Executor {
+    backend: Backend,
+    pub maps: MapCache, // Kernels generated for map expressions, shared by every backend.
 }
 
 // Public impl
 impl Executor {
-    // Create a new ```Executor``` with populated adapter and operations fields.
-    pub async fn new(shader_path_directory: &str) -> Result<Self, String> {
-        let mut ex = Executor::default();
-        let adapter = Executor::get_adapter_info().await?;
-        // TODO: Switch this to add shader modules only when you stage the associated function
-        let shaders =
-            Executor::add_shader_modules_from_directory(&adapter.device, shader_path_directory)
-                .await;
-
-        if let Some(shaders) = shaders {
-            ex.shaders = Some(Box::new(shaders))
-        } else {
-            ex.shaders = None
+    /// Creates the backend `options` asks for. [BACKEND_ENV] takes precedence over the builder's choice.
+    pub async fn new(options: &ContextBuilder) -> Result<Self, Error> {
+        let kind = match std::env::var(BACKEND_ENV) {
+            Ok(value) => {
+                let kind = value.parse::<BackendKind>()?;
+                debug!("{} overrides the backend selection with {:?}", BACKEND_ENV, kind);
+                kind
+            }
+            Err(_) => options.backend,
+        };
+
+        let backend = match kind {
+            BackendKind::Gpu => Backend::Gpu(GpuBackend::new(options).await?),
+            BackendKind::Cpu => Backend::Cpu(CpuBackend::new(options.pool_capacity, options.memory_limit)),
+            BackendKind::Auto => match GpuBackend::new(options).await {
+                Ok(gpu) => Backend::Gpu(gpu),
+                Err(Error::NoAdapter) => {
+                    warn!("No GPU adapter found, falling back to the CPU backend");
+                    Backend::Cpu(CpuBackend::new(options.pool_capacity, options.memory_limit))
+                }
+                Err(e) => return Err(e),
+            },
+        };
+
+        Ok(Executor {
+            backend,
+            maps: MapCache::new(options.map_cache_capacity),
+        })
+    }
+
+    /// The kind of backend actually in use; never [BackendKind::Auto].
+    pub fn kind(&self) -> BackendKind {
+        match self.backend {
+            Backend::Gpu(_) => BackendKind::Gpu,
+            Backend::Cpu(_) => BackendKind::Cpu,
         }
-        ex.adapter = Some(Box::new(adapter));
+    }
 
-        Ok(ex)
+    /// Information about the GPU adapter, or [None] on the CPU backend.
+    pub fn adapter_info(&self) -> Option<AdapterInfo> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.adapter.as_ref().map(|adapter| adapter.info.clone()),
+            Backend::Cpu(_) => None,
+        }
     }
 
     // Prints Executor fields for debugging. Must have log_level set to debug
     pub fn info(&self) {
-        debug!("{:?}", self.shaders);
-        debug!("{:?}", self.adapter);
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.info(),
+            Backend::Cpu(cpu) => debug!("{:?}", cpu),
+        }
+    }
+
+    /// Compiles the kernels in `names` ahead of their first use. The CPU backend has nothing to compile.
+    pub async fn prewarm(&self, names: &[String]) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.prewarm(names).await,
+            Backend::Cpu(_) => Ok(()),
+        }
     }
 
     pub fn drop(&self, id: &String) {
-        self.buffers.write().unwrap().remove(id);
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.drop(id),
+            Backend::Cpu(cpu) => cpu.drop(id),
+        }
     }
 
-    /// Sets up storage and staging (input, output) buffers and adds them to the executor
-    pub async fn setup_buffers<T>(&self, dimensions: &[usize; 4], data: &[T], id: String) -> Result<(), String>
-    where
-        T: Pod,
-    {
-        let Some(ref adapter) = self.adapter else {
-            return Err("No operations loaded".parse().unwrap());
-        };
-        // Instantiates buffer with data (`numbers`).
-        // Usage allowing the buffer to be:
-        //   A storage buffer (can be bound within a bind group and thus available to a shader).
-        //   The destination of a copy.
-        //   The source of a copy.
-        let storage_buffer = adapter
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Storage Buffer"),
-                contents: bytemuck::cast_slice::<T, u8>(&data),
-                usage: wgpu::BufferUsages::STORAGE
-                    | wgpu::BufferUsages::COPY_DST
-                    | wgpu::BufferUsages::COPY_SRC,
-            });
-
-        // Instantiates buffer without data.
-        // `usage` of buffer specifies how it can be used:
-        //   `BufferUsages::MAP_READ` allows it to be read (outside the shader).
-        //   `BufferUsages::COPY_DST` allows it to be the destination of the copy.
-        let staging_buffer = adapter.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Staging Buffer"),
-            size: storage_buffer.size(),
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let dimensions_buffer = adapter.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Dimensions Buffer"),
-            contents:  bytemuck::cast_slice::<usize, u8>(dimensions),
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_DST
-        });
-
-        self.buffers.write().unwrap().insert(
-            id.clone(),
-            Buffers {
-                storage_buffer,
-                staging_buffer,
-                dimensions_buffer
-            }
-        );
+    /// Drops the idle buffers of the pool.
+    pub fn empty_cache(&self) {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.empty_cache(),
+            Backend::Cpu(cpu) => cpu.empty_cache(),
+        }
+    }
 
-        Ok(())
+    pub fn pool_stats(&self) -> PoolStats {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.pool_stats(),
+            Backend::Cpu(cpu) => cpu.pool_stats(),
+        }
     }
 
-    /// Test function.
-    /// Doubles the array input
-    pub async fn execute_op(&self, id: &String, operation: Operation) -> Result<Vec<u32>, String> {
-        // Instantiate our Executor
-        let Some(adapter) = self.adapter.as_ref() else {
-            return Err("Not operations loaded".parse().unwrap());
-        };
-        let device = &adapter.device;
-        let queue = &adapter.queue;
-        let Some(shaders) = self.shaders.as_ref() else {
-            return Err("Not operations loaded".parse().unwrap());
+    pub fn cache_stats(&self) -> CacheStats {
+        let stats = match &self.backend {
+            Backend::Gpu(gpu) => gpu.cache_stats(),
+            Backend::Cpu(_) => CacheStats::default(),
         };
 
-        // Get our buffers from our data
-        let buffers = self.buffers.read().unwrap();
-        let buffer = buffers.get(id).unwrap();
-        let staging_buffer = &buffer.staging_buffer;
-        let storage_buffer = &buffer.storage_buffer;
-        let dimensions_buffer = &buffer.dimensions_buffer;
-
-        // A bind group defines how buffers are accessed by operations.
-        // It is to WebGPU what a descriptor set is to Vulkan.
-        // `binding` here refers to the `binding` of a buffer in the shader (`layout(set = 0, binding = 0) buffer`).
-        // Instantiates the bind group, once again specifying the binding of buffers.
-        // let bind_group_layout = compute_pipeline.get_bind_group_layout(0);
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("bind_group_layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage {
-                            read_only: false,
-                        },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage {
-                            read_only: true,
-                        },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }
-            ]
-        });
-
-        // Now we need to create our bind groups with our buffers.
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: storage_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: dimensions_buffer.as_entire_binding(),
-                }
-            ],
-        });
-
-        // We need to define the layout of our pipeline (shader in this case) we're using as well.
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        // A pipeline specifies the operation of a shader
-        // Instantiates the pipeline.
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Compute Pipeline"),
-            layout: Some(&pipeline_layout),
-            module: shaders.index(decode_operation(operation)),
-            entry_point: Some("main"),
-            compilation_options: Default::default(),
-            cache: None,
-        });
-
-        // A command encoder executes one or many pipelines.
-        // It is to WebGPU what a command buffer is to Vulkan.
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&compute_pipeline);
-            cpass.set_bind_group(0, &bind_group, &[]);
-            cpass.insert_debug_marker("");
-            cpass.dispatch_workgroups(storage_buffer.size() as u32, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
+        CacheStats {
+            map_kernels: self.maps.len(),
+            ..stats
         }
-        // Sets adds copy operation to command encoder.
-        // Will copy data from storage buffer on GPU to staging buffer on CPU.
-        encoder.copy_buffer_to_buffer(
-            storage_buffer,
-            0,
-            staging_buffer,
-            0,
-            staging_buffer.size(),
-        );
-
-        // Submits command encoder for processing
-        queue.submit(Some(encoder.finish()));
-
-        // Note that we're not calling `.await` here.
-        let buffer_slice = staging_buffer.slice(..);
-        // Sets the buffer up for mapping, sending over the result of the mapping back to us when it is finished.
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-
-        // Poll the device in a blocking manner so that our future resolves.
-        // In an actual application, `device.poll(...)` should
-        // be called in an event loop or on another thread.
-        device.poll(wgpu::Maintain::wait()).panic_on_timeout();
-
-        // Awaits until `buffer_future` can be read from
-        if let Ok(Ok(())) = receiver.recv_async().await {
-            // Gets contents of buffer
-            let data = buffer_slice.get_mapped_range();
-            // Since contents are got in bytes, this converts these bytes back to u32
-            let result: Vec<u32> = bytemuck::cast_slice(&data).to_vec();
-
-            // With the current interface, we have to make sure all mapped views are
-            // dropped before we unmap the buffer.
-            drop(data);
-            staging_buffer.unmap(); // Unmaps buffer from memory
-                                    // If you are familiar with C++ these 2 lines can be thought of similarly to:
-                                    //   delete myPointer;
-                                    //   myPointer = NULL;
-                                    // It effectively frees the memory
-
-            Ok(result)
-        } else {
-            Err("failed to run compute on gpu!".into())
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.memory_stats(),
+            Backend::Cpu(cpu) => cpu.memory_stats(),
         }
     }
-}
 
-// Private impl
-impl Executor {
-    /// Get device description. Should return the highest performance device on a system. Should only be called once unless you need to request another adapter.
-    async fn get_adapter_info() -> Result<GpuHandle, String> {
-        // Creates adapters and surfaces using the information in the ```InstanceDescriptor```
-        let instance = wgpu::Instance::new(&InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            backend_options: wgpu::BackendOptions {
-                gl: wgpu::GlBackendOptions {
-                    gles_minor_version: Default::default(), // Select which minor version of Open GL to use.
-                },
-                dx12: wgpu::Dx12BackendOptions {
-                    shader_compiler: Default::default(),
-                }
-            },
-            flags: InstanceFlags::empty(), // Instance flags for debugging.
-        });
-
-        // Gives us a handle to all gpu compute adapters with the given ```RequestAdapterOptions```
-        let Some(adapter) = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: PowerPreference::HighPerformance, // HighPerformance will tell it to return adapters that offer higher performance, like GPUs.
-                force_fallback_adapter: false, // If true, will force WGPU to use an adapter that is supported by all hardware.
-                compatible_surface: None, // If given a surface (like a window / display) it will return adapters that can present to that surface.
-            })
-            .await
-        else {
-            return Err("Found no adapters.".parse().unwrap());
-        };
+    /// Sets up buffers holding `data` under `id`.
+    pub async fn setup_buffers<T: Pod>(&self, dimensions: &[usize; 4], data: &[T], id: String) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.setup_buffers(data, id).await,
+            Backend::Cpu(cpu) => cpu.setup_buffers(dimensions, data, id),
+        }
+    }
 
-        debug!("Adapter(s) = {:?}", adapter.get_info());
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Device 1"),                // Debug label
-                    required_features: Features::empty(), // Define a list of features that the device must implement.
-                    required_limits: Default::default(), // Defines a list of limits of certain types of resources that we can create.
-                    memory_hints: MemoryHints::MemoryUsage, // Defines memory allocation hints for our device.
-                },
-                None, // Typically a path used for tracing api calls.
-            )
-            .await
-            .expect("Error requesting device.");
-
-        Ok(GpuHandle::new(device, queue))
+    /// Sets up buffers for `size` bytes of zero bits under `id`.
+    pub async fn allocate_buffers(&self, dimensions: &[usize; 4], size: u64, id: String) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.allocate_buffers(size, id).await,
+            Backend::Cpu(cpu) => cpu.allocate_buffers(dimensions, size, id),
+        }
     }
 
-    /// Returns a list of [ShaderModule] after being given a list of shader paths
-    async fn add_shader_modules<'a>(
-        device: &Device,
-        shader_paths: &[String],
-    ) -> Option<ShaderResources> {
-        let mut shader_module_hm = HashMap::new();
-
-        // iterate paths in shader_paths and create shader modules
-        for path in shader_paths {
-            let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(path),
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(
-                    &*std::fs::read_to_string(path).unwrap(),
-                )),
-            });
-            shader_module_hm.insert(path.to_owned(), cs_module);
+    /// Sets every byte of the array to zero.
+    pub async fn clear(&self, id: &String) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.clear(id).await,
+            Backend::Cpu(cpu) => cpu.clear(id),
         }
-        Some(shader_module_hm)
     }
 
-    /// Returns a list of [ShaderModule]s from a given directory
-    async fn add_shader_modules_from_directory<'a>(
-        device: &Device,
-        shaders_directory: &str,
-    ) -> Option<ShaderResources> {
-        let mut shader_module_hm = HashMap::new();
+    /// Copies the contents of `source` into `destination`, an array of the same size.
+    pub async fn copy(&self, source: &String, destination: &String) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.copy(source, destination).await,
+            Backend::Cpu(cpu) => cpu.copy(source, destination),
+        }
+    }
 
-        let shader_paths = match std::fs::read_dir(shaders_directory) {
-            Ok(s) => s,
-            Err(_) => return None,
+    /// Runs a built-in [Operation]. Nothing is read back; the result stays in the output array.
+    pub async fn launch(&self, launch: &Launch<'_>) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.launch(launch).await,
+            Backend::Cpu(cpu) => cpu.launch(launch),
         }
-        .map(|path| path.unwrap().path().into_os_string().into_string().unwrap())
-        .collect::<Vec<String>>();
-
-        // Iterate paths and create shader modules out of them
-        for path in shader_paths.iter() {
-            let file_name = path
-                .strip_prefix(shaders_directory)
-                .unwrap()
-                .strip_prefix("\\")
-                .unwrap()
-                .strip_suffix(".wgsl")
-                .unwrap();
-
-            let shader: Cow<str> = Cow::from(
-                std::fs::read_to_string(path)
-                    .expect(format!("Could not read file contents from: {}", path).as_str()),
-            );
-            let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(file_name),
-                source: wgpu::ShaderSource::Wgsl(shader),
-            });
-            shader_module_hm.insert(file_name.to_owned(), cs_module);
+    }
+
+    /// Evaluates a fused map expression for the first `len` elements of the inputs, writing to `output`.
+    pub async fn run_fused(&self, kernel: &FusedKernel, output: &String, inputs: &[&String], len: usize) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.run_fused(kernel, output, inputs, len).await,
+            Backend::Cpu(cpu) => cpu.run_fused(kernel, output, inputs, len),
         }
+    }
 
-        Some(shader_module_hm)
+    /// Adds a custom WGSL kernel. Only the GPU backend can run them.
+    pub async fn register_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.register_kernel(kernel).await,
+            Backend::Cpu(_) => Err(Error::Unsupported(format!("custom kernel {:?} needs the GPU backend", kernel.name))),
+        }
+    }
+
+    /// Adds a custom WGSL kernel unless one of the same name is registered already.
+    pub async fn ensure_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.ensure_kernel(kernel).await,
+            Backend::Cpu(_) => Err(Error::Unsupported(format!("custom kernel {:?} needs the GPU backend", kernel.name))),
+        }
+    }
+
+    /// Runs a custom kernel registered with [Executor::register_kernel].
+    pub async fn run_kernel(&self, name: &str, inputs: &[&String], outputs: &[&String], params: &[u32], grid: Grid) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.run_kernel(name, inputs, outputs, params, grid).await,
+            Backend::Cpu(_) => Err(Error::Unsupported(format!("custom kernel {:?} needs the GPU backend", name))),
+        }
+    }
+
+    /// Starts holding back GPU commands until the batch ends. The CPU backend runs everything right away regardless.
+    pub fn begin_batch(&self) {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.begin_batch(),
+            Backend::Cpu(_) => {}
+        }
+    }
+
+    /// Ends a batch without waiting for the device.
+    pub fn end_batch(&self) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.end_batch(),
+            Backend::Cpu(_) => Ok(()),
+        }
+    }
+
+    /// Ends a batch and waits until the device has finished everything submitted.
+    pub async fn finish_batch(&self) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.finish_batch().await,
+            Backend::Cpu(_) => Ok(()),
+        }
+    }
+
+    /// Copies the contents of the array back to the host.
+    pub async fn read<T: Pod>(&self, id: &String) -> Result<Vec<T>, Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.read(id).await,
+            Backend::Cpu(cpu) => cpu.read(id),
+        }
+    }
+
+    /// Copies the elements in `runs` back to the host, one run after the other.
+    pub async fn read_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>]) -> Result<Vec<T>, Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.read_runs(id, runs).await,
+            Backend::Cpu(cpu) => cpu.read_runs(id, runs),
+        }
+    }
+
+    /// Overwrites the elements in `runs` with `data`, which holds the new contents of one run after the other.
+    pub async fn write_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>], data: &[T]) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.write_runs(id, runs, data).await,
+            Backend::Cpu(cpu) => cpu.write_runs(id, runs, data),
+        }
+    }
+
+    /// Test function.
+    /// Doubles the array input
+    pub async fn execute_op(&self, id: &String, operation: Operation) -> Result<Vec<u32>, Error> {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu.execute_op(id, operation).await,
+            Backend::Cpu(cpu) => cpu.execute_op(id, operation),
+        }
     }
 }
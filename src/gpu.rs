@@ -0,0 +1,923 @@
+use crate::adapter::select_adapter;
+use crate::context::ContextBuilder;
+use crate::error::Error;
+use crate::execution::{decode_operation, Launch, Operation};
+use crate::kernel::{Grid, Kernel};
+use crate::lru::Lru;
+use crate::map::{CacheStats, DEFAULT_MAP_CACHE_CAPACITY};
+use crate::memory::{Allocation, Category, MemoryStats, MemoryTracker};
+use crate::graph::FusedKernel;
+use crate::pipeline::{PipelineKey, Pipelines};
+use crate::poller::Poller;
+use crate::pool::{self, BufferPool, PoolStats};
+use crate::shaders::ShaderCache;
+use crate::staging::{StagingBuffer, StagingRing, DEFAULT_STAGING_BUDGET};
+use crate::turns::Turns;
+use crate::dispatch::DispatchPlan;
+use bytemuck::Pod;
+use log::{debug, error};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
+use wgpu::util::DeviceExt;
+use wgpu::{AdapterInfo, Buffer, BufferUsages, CommandEncoder, Device, ErrorFilter, Features, Limits, MemoryHints, Queue};
+
+/// GpuHandle
+/// This will hold our [Device] and [Queue] for later executions
+#[derive(Debug)]
+pub struct GpuHandle {
+    pub info: AdapterInfo,
+    pub device: Box<Device>,
+    pub queue: Box<Queue>,
+    lost: Arc<Mutex<Option<String>>>, // Set by wgpu once the device is lost, after which nothing can run on it.
+    poller: Poller,
+    scope_turns: Turns, // See [GpuHandle::scoped].
+}
+
+#[derive(Debug)]
+pub struct Buffers {
+    size: u64, // Bytes of actual data, spread over the chunks in order.
+    chunks: Vec<Buffer>, // Storage buffers laid out by [GpuBackend::layout]; just one unless the array is too large to bind at once.
+    allocation: Allocation, // Of the storage buffers; counted as pooled while the buffers wait in the pool.
+}
+
+impl Buffers {
+    /// Bytes the chunks hold together, which is also the pool bucket they go back to.
+    fn capacity(&self) -> u64 {
+        self.chunks.iter().map(Buffer::size).sum()
+    }
+}
+
+/// Pairs every chunk holding part of the first `size` bytes with the range of those bytes it holds.
+fn spans(chunks: &[Buffer], size: u64) -> impl Iterator<Item = (&Buffer, Range<u64>)> {
+    let mut offset = 0;
+    chunks.iter().map_while(move |chunk| {
+        if offset >= size {
+            return None;
+        }
+        let span = offset..(offset + chunk.size()).min(size);
+        offset = span.end;
+        Some((chunk, span))
+    })
+}
+
+/// Splits the bytes `range` of an array into the parts held by each chunk, as `(chunk, offset in the chunk, len)`.
+fn pieces(chunks: &[Buffer], range: Range<u64>) -> impl Iterator<Item = (&Buffer, u64, u64)> {
+    spans(chunks, range.end).filter_map(move |(chunk, span)| {
+        let start = span.start.max(range.start);
+        (start < span.end).then(|| (chunk, start - span.start, span.end - start))
+    })
+}
+
+/// The largest storage buffer `limits` allow to bind, which is what arrays are split into chunks of.
+fn chunk_size(limits: &Limits) -> u64 {
+    let max = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+
+    max - max % wgpu::COPY_BUFFER_ALIGNMENT
+}
+
+/// Commands waiting to be submitted while a [crate::CommandBatch] is open.
+#[derive(Debug, Default)]
+struct Recorder {
+    encoder: Option<CommandEncoder>,
+    batches: usize, // Batches currently open; commands are only held back while there is at least one.
+    retired: Vec<Buffers>, // Buffers of arrays dropped while commands using them were held back; pooled once those are submitted.
+}
+
+/// How a buffer is bound to a kernel. The position in the list passed to [GpuBackend::dispatch] is the `@binding` index.
+pub enum Binding<'a> {
+    Storage(&'a Buffer),
+    ReadOnly(&'a Buffer),
+    Uniform(&'a Buffer),
+}
+
+impl Binding<'_> {
+    fn buffer(&self) -> &Buffer {
+        match self {
+            Binding::Storage(buffer) | Binding::ReadOnly(buffer) | Binding::Uniform(buffer) => buffer,
+        }
+    }
+
+    fn ty(&self) -> wgpu::BufferBindingType {
+        match self {
+            Binding::Storage(_) => wgpu::BufferBindingType::Storage { read_only: false },
+            Binding::ReadOnly(_) => wgpu::BufferBindingType::Storage { read_only: true },
+            Binding::Uniform(_) => wgpu::BufferBindingType::Uniform,
+        }
+    }
+}
+
+impl GpuHandle {
+    pub fn new(info: AdapterInfo, device: Device, queue: Queue) -> Result<Self, Error> {
+        let lost = Arc::new(Mutex::new(None));
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            *lost_flag.lock().unwrap() = Some(format!("{:?}: {}", reason, message));
+        });
+        // Errors are captured with error scopes around every call; anything that slips through is logged
+        // instead of taking the default handler's panic.
+        device.on_uncaptured_error(Box::new(|e| error!("Uncaptured wgpu error: {}", e)));
+        let poller = Poller::new(device.clone())?;
+        Ok(GpuHandle {
+            info,
+            device: Box::new(device),
+            queue: Box::new(queue),
+            lost,
+            poller,
+            scope_turns: Turns::new(),
+        })
+    }
+
+    /// Waits until the device has finished everything submitted so far, without blocking the calling thread.
+    pub async fn wait_idle(&self) -> Result<(), Error> {
+        let (sender, receiver) = flume::bounded(1);
+        self.queue.on_submitted_work_done(move || {
+            let _ = sender.send(());
+        });
+        self.poller.wake();
+
+        receiver
+            .recv_async()
+            .await
+            .map_err(|_| Error::DeviceLost("the device dropped the completion callback".into()))?;
+        self.check_lost()
+    }
+
+    /// Fails with [Error::DeviceLost] once wgpu has reported the device as lost.
+    fn check_lost(&self) -> Result<(), Error> {
+        match self.lost.lock().unwrap().as_ref() {
+            Some(reason) => Err(Error::DeviceLost(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `f` inside out-of-memory and validation error scopes, turning anything wgpu reports into an [Error].
+    /// wgpu keeps a single stack of error scopes per device, shared by every thread, so callers take turns: otherwise
+    /// one could pop the scopes another pushed and report its errors. `f` must not call `scoped` itself.
+    pub(crate) async fn scoped<R>(&self, f: impl FnOnce(&Device) -> R) -> Result<R, Error> {
+        let _turn = self.scope_turns.take().await;
+
+        self.device.push_error_scope(ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let result = f(&self.device);
+        let validation = self.device.pop_error_scope().await;
+        let out_of_memory = self.device.pop_error_scope().await;
+
+        if let Some(e) = out_of_memory {
+            return Err(Error::OutOfMemory(e.to_string()));
+        }
+        if let Some(e) = validation {
+            return Err(Error::Validation(e.to_string()));
+        }
+        self.check_lost()?;
+
+        Ok(result)
+    }
+}
+
+/// GPU backend of the [crate::execution::Executor]. Holds the [ShaderCache], [GpuHandle] and [Buffer]s for dynamically executing commands on the GPU
+#[derive(Debug)]
+pub struct GpuBackend {
+    pub adapter: Option<Box<GpuHandle>>,
+    pub shaders: Option<Box<ShaderCache>>,
+    pipelines: Option<Box<Pipelines>>,
+    kernels: RwLock<HashMap<String, Kernel>>, // Custom kernels registered by the user, by name.
+    buffers: Arc<RwLock<HashMap<String, Buffers>>>,
+    recorder: Mutex<Recorder>,
+    pool: BufferPool<Buffers>,
+    staging: StagingRing,
+    memory: Arc<MemoryTracker>,
+    chunk_size: u64,
+    fused: Box<Lru<()>>, // Fused kernels whose shaders and pipelines are kept, by name.
+}
+
+impl Default for GpuBackend {
+    fn default() -> Self {
+        GpuBackend {
+            adapter: None,
+            shaders: None,
+            pipelines: None,
+            kernels: RwLock::new(HashMap::new()),
+            buffers: Arc::new(RwLock::new(HashMap::new())), // RwLock locks the value so that there can only be one writer at a time. Also, can be used for interior mutability.
+            recorder: Mutex::new(Recorder::default()),
+            pool: BufferPool::new(pool::DEFAULT_POOL_CAPACITY),
+            staging: StagingRing::new(DEFAULT_STAGING_BUDGET),
+            memory: MemoryTracker::new(None),
+            chunk_size: chunk_size(&Limits::default()),
+            fused: Box::new(Lru::new(DEFAULT_MAP_CACHE_CAPACITY)),
+        }
+    }
+}
+
+// Public impl
+impl GpuBackend {
+    // Create a new ```GpuBackend``` with populated adapter and operations fields.
+    pub async fn new(options: &ContextBuilder) -> Result<Self, Error> {
+        let mut ex = GpuBackend::default();
+        let adapter = GpuBackend::get_adapter_info(options).await?;
+        // Shaders are only compiled once an operation needs them, apart from the ones asked to be prewarmed.
+        let shaders = ShaderCache::new(options.shader_directory.as_deref())?;
+        shaders.prewarm(&adapter, &options.prewarm).await?;
+
+        ex.shaders = Some(Box::new(shaders));
+        ex.pipelines = Some(Box::new(Pipelines::new(&adapter, options.pipeline_cache_directory.as_deref())));
+        ex.chunk_size = chunk_size(&adapter.device.limits());
+        ex.adapter = Some(Box::new(adapter));
+        ex.pool = BufferPool::new(options.pool_capacity);
+        ex.staging = StagingRing::new(options.staging_budget);
+        ex.memory = MemoryTracker::new(options.memory_limit);
+        // The kernel being run is always kept, so at least one has to fit.
+        ex.fused = Box::new(Lru::new(options.map_cache_capacity.max(1)));
+
+        Ok(ex)
+    }
+
+    // Prints GpuBackend fields for debugging. Must have log_level set to debug
+    pub fn info(&self) {
+        debug!("{:?}", self.shaders);
+        debug!("{:?}", self.adapter);
+    }
+
+    /// Compiles the shaders of the kernels in `names` now instead of on their first use.
+    pub async fn prewarm(&self, names: &[String]) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let Some(shaders) = self.shaders.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+
+        shaders.prewarm(adapter, names).await
+    }
+
+    /// Frees the buffers of the array, handing them to the pool for reuse.
+    pub fn drop(&self, id: &String) {
+        let Some(mut buffers) = self.buffers.write().unwrap().remove(id) else {
+            return;
+        };
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.encoder.is_some() {
+            // Recorded commands may still use the buffers, so a new array mustn't get them before those are submitted.
+            recorder.retired.push(buffers);
+        } else {
+            buffers.allocation.move_to(Category::Pool);
+            self.pool.give(buffers.capacity(), buffers);
+        }
+    }
+
+    /// Drops every idle buffer the pool holds, along with the idle staging buffers.
+    pub fn empty_cache(&self) {
+        self.pool.clear();
+        self.staging.clear();
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// How many fused kernels, shader modules and pipelines the device keeps.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            fused_kernels: self.fused.len(),
+            shaders: self.shaders.as_deref().map_or(0, ShaderCache::modules),
+            pipelines: self.pipelines.as_deref().map_or(0, Pipelines::len),
+            ..CacheStats::default()
+        }
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            arrays: self.buffers.read().unwrap().len(),
+            ..self.memory.stats()
+        }
+    }
+
+    /// Sets up the storage buffers holding `data` and adds them to the executor
+    pub async fn setup_buffers<T>(&self, data: &[T], id: String) -> Result<(), Error>
+    where
+        T: Pod,
+    {
+        let adapter = self.handle()?;
+        let contents = bytemuck::cast_slice::<T, u8>(data);
+        let (buffers, _) = self.acquire(adapter, contents.len() as u64).await?;
+        // Empty arrays have nothing to upload, and no spans either.
+        adapter.scoped(|_| {
+            for (chunk, span) in spans(&buffers.chunks, buffers.size) {
+                adapter.queue.write_buffer(chunk, 0, &contents[span.start as usize..span.end as usize]);
+            }
+        }).await?;
+
+        self.buffers.write().unwrap().insert(id, buffers);
+
+        Ok(())
+    }
+
+    /// Sets up buffers for `size` bytes without uploading anything from the host.
+    /// wgpu zero initializes new buffers, and recycled ones are cleared, so the contents start out as all zero bits.
+    pub async fn allocate_buffers(&self, size: u64, id: String) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let (buffers, recycled) = self.acquire(adapter, size).await?;
+        if recycled {
+            adapter.scoped(|_| {
+                self.record(adapter, |encoder| {
+                    for chunk in &buffers.chunks {
+                        encoder.clear_buffer(chunk, 0, None);
+                    }
+                })
+            }).await?;
+        }
+
+        self.buffers.write().unwrap().insert(id, buffers);
+
+        Ok(())
+    }
+
+    /// Creates a uniform buffer holding kernel parameters.
+    /// The words are padded with zeros to a multiple of 16 bytes, the alignment of uniform structs.
+    pub fn create_uniform(&self, params: &[u32]) -> Result<Buffer, Error> {
+        let adapter = self.handle()?;
+        let mut words = params.to_vec();
+        words.resize(params.len().next_multiple_of(4).max(4), 0);
+        Ok(adapter.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Params Buffer"),
+            contents: bytemuck::cast_slice(&words),
+            usage: BufferUsages::UNIFORM,
+        }))
+    }
+
+    /// Returns a handle to the storage buffer of the array with the given id.
+    /// Fails with [Error::Unsupported] for arrays split into several chunks, which can't be bound as a whole.
+    pub fn storage_buffer(&self, id: &String) -> Result<Buffer, Error> {
+        match self.chunks(id)?.1.as_slice() {
+            [chunk] => Ok(chunk.clone()),
+            chunks => Err(Error::Unsupported(format!(
+                "array {} spans {} buffers, so it can't be bound to a single binding",
+                id,
+                chunks.len()
+            ))),
+        }
+    }
+
+    /// Returns the size of the array with the given id and handles to all of its chunks.
+    pub fn chunks(&self, id: &String) -> Result<(u64, Vec<Buffer>), Error> {
+        let buffers = self.buffers.read().unwrap();
+        match buffers.get(id) {
+            Some(buffer) => Ok((buffer.size, buffer.chunks.clone())),
+            None => Err(Error::UnknownArray(id.clone())),
+        }
+    }
+
+    /// Sets every byte of the array's storage buffers to zero.
+    pub async fn clear(&self, id: &String) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let (_, chunks) = self.chunks(id)?;
+        adapter.scoped(|_| {
+            self.record(adapter, |encoder| {
+                for chunk in &chunks {
+                    encoder.clear_buffer(chunk, 0, None);
+                }
+            })
+        }).await
+    }
+
+    /// Copies the contents of the storage buffers of `source` into those of `destination`, which must be as large.
+    pub async fn copy(&self, source: &String, destination: &String) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let (_, source) = self.chunks(source)?;
+        let (_, destination) = self.chunks(destination)?;
+        // Arrays of the same size have the same layout, so the chunks line up.
+        adapter.scoped(|_| {
+            self.record(adapter, |encoder| {
+                for (source, destination) in source.iter().zip(&destination) {
+                    encoder.copy_buffer_to_buffer(source, 0, destination, 0, source.size());
+                }
+            })
+        }).await
+    }
+
+    /// Runs the kernel `name` over the grid of workgroups in `plan`, with `bindings` bound in order to group 0.
+    /// Nothing is read back; the results stay in the bound buffers.
+    pub async fn dispatch(&self, name: &str, bindings: &[Binding<'_>], plan: &DispatchPlan) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let Some(shaders) = self.shaders.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        let module = shaders.module(adapter, name).await?;
+
+        let Some(pipelines) = self.pipelines.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        let key = PipelineKey {
+            kernel: name.to_string(),
+            workgroup_size: plan.workgroup_size,
+            layout: bindings.iter().map(Binding::ty).collect(),
+        };
+        // Mismatches between the bindings and the shader show up as validation errors when the pipeline is created.
+        let cached = pipelines.get_or_create(adapter, &key, &module).await?;
+
+        adapter.scoped(|device| {
+            // A bind group defines how buffers are accessed by operations.
+            // It is to WebGPU what a descriptor set is to Vulkan.
+            let group_entries = bindings
+                .iter()
+                .enumerate()
+                .map(|(i, binding)| wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource: binding.buffer().as_entire_binding(),
+                })
+                .collect::<Vec<_>>();
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bind Group"),
+                layout: &cached.bind_group_layout,
+                entries: &group_entries,
+            });
+
+            // A command encoder executes one or many pipelines.
+            // It is to WebGPU what a command buffer is to Vulkan.
+            self.record(adapter, |encoder| {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                cpass.set_pipeline(&cached.pipeline);
+                cpass.set_bind_group(0, &bind_group, &[]);
+                cpass.insert_debug_marker("");
+                let [x, y, z] = plan.workgroups;
+                cpass.dispatch_workgroups(x, y, z); // Number of workgroups to run along x, y and z
+            });
+        }).await
+    }
+
+    /// Runs a built-in [Operation]: binding 0 is the output, the inputs follow read-only and the parameters come last as a uniform.
+    /// Outputs split into chunks are processed one chunk at a time, with the index of its first element appended to the parameters.
+    pub async fn launch(&self, launch: &Launch<'_>) -> Result<(), Error> {
+        let inputs = launch.inputs.iter().map(|id| self.chunks(id).map(|(_, chunks)| chunks)).collect::<Result<Vec<_>, _>>()?;
+        // The source of a tile is indexed freely, so it has to be bound whole to every chunk of the output.
+        if launch.operation == Operation::Tile && inputs.iter().any(|chunks| chunks.len() > 1) {
+            return Err(Error::Unsupported("the source of a tile must fit into a single buffer".into()));
+        }
+
+        self.dispatch_chunks(decode_operation(launch.operation), launch.output, &inputs, launch.len, |first, _| {
+            let mut params = launch.params.to_vec();
+            params.push(first as u32);
+            params
+        }).await
+    }
+
+    /// Evaluates a fused map expression for the first `len` elements, writing to `output`.
+    pub async fn run_fused(&self, kernel: &FusedKernel, output: &String, inputs: &[&String], len: usize) -> Result<(), Error> {
+        let Some(shaders) = self.shaders.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        if self.fused.get(&kernel.name).is_none() {
+            // Evicting a kernel another thread is about to run makes that run fail to find its shader, which takes
+            // as many other fused kernels as the cache holds to be compiled in between.
+            if let (_, Some(evicted)) = self.fused.insert(kernel.name.clone(), ()) {
+                shaders.remove(&evicted);
+                if let Some(pipelines) = self.pipelines.as_deref() {
+                    pipelines.remove(&evicted);
+                }
+            }
+        }
+        shaders.insert(&kernel.name, &kernel.source);
+
+        let inputs = inputs.iter().map(|id| self.chunks(id).map(|(_, chunks)| chunks)).collect::<Result<Vec<_>, _>>()?;
+        // Every element only depends on the same element of the inputs, so the chunks are independent.
+        self.dispatch_chunks(&kernel.name, output, &inputs, len, |_, count| vec![count as u32]).await
+    }
+
+    /// Adds a custom kernel. Its source is compiled right away.
+    pub async fn register_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let Some(shaders) = self.shaders.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        shaders.register(adapter, &kernel.name, kernel.source.clone()).await?;
+        self.kernels.write().unwrap().insert(kernel.name.clone(), kernel);
+
+        Ok(())
+    }
+
+    /// Adds a custom kernel unless one of the same name is registered already.
+    /// Callers racing to register the same kernel all succeed, so its source must only depend on the name.
+    pub async fn ensure_kernel(&self, kernel: Kernel) -> Result<(), Error> {
+        if self.kernels.read().unwrap().contains_key(&kernel.name) {
+            return Ok(());
+        }
+        let Some(shaders) = self.shaders.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        if !shaders.contains(&kernel.name) {
+            match shaders.register(self.handle()?, &kernel.name, kernel.source.clone()).await {
+                Err(Error::InvalidArgument(_)) if shaders.contains(&kernel.name) => {} // Lost the race to another caller.
+                result => result?,
+            }
+        }
+        self.kernels.write().unwrap().entry(kernel.name.clone()).or_insert(kernel);
+
+        Ok(())
+    }
+
+    /// Runs a custom kernel with `inputs` bound read-only, followed by `outputs` and the uniform `params`.
+    pub async fn run_kernel(&self, name: &str, inputs: &[&String], outputs: &[&String], params: &[u32], grid: Grid) -> Result<(), Error> {
+        let Some(kernel) = self.kernels.read().unwrap().get(name).cloned() else {
+            return Err(Error::InvalidArgument(format!("no custom kernel named {:?} is registered", name)));
+        };
+        if inputs.len() != kernel.inputs || outputs.len() != kernel.outputs {
+            return Err(Error::InvalidArgument(format!(
+                "kernel {:?} takes {} inputs and {} outputs, but {} and {} were given",
+                name,
+                kernel.inputs,
+                kernel.outputs,
+                inputs.len(),
+                outputs.len()
+            )));
+        }
+        if kernel.uniform == params.is_empty() {
+            return Err(Error::InvalidArgument(if kernel.uniform {
+                format!("kernel {:?} takes parameters, but none were given", name)
+            } else {
+                format!("kernel {:?} takes no parameters, but {} were given", name, params.len())
+            }));
+        }
+
+        let inputs = inputs.iter().map(|id| self.storage_buffer(id)).collect::<Result<Vec<_>, _>>()?;
+        let outputs = outputs.iter().map(|id| self.storage_buffer(id)).collect::<Result<Vec<_>, _>>()?;
+        let uniform = if kernel.uniform { Some(self.create_uniform(params)?) } else { None };
+
+        let mut bindings = inputs.iter().map(Binding::ReadOnly).collect::<Vec<_>>();
+        bindings.extend(outputs.iter().map(Binding::Storage));
+        bindings.extend(uniform.iter().map(Binding::Uniform));
+
+        let plan = match grid {
+            Grid::Elements(len) => DispatchPlan::new(len, &self.handle()?.device.limits())?,
+            Grid::Workgroups(workgroups) => DispatchPlan {
+                workgroup_size: None,
+                workgroups,
+            },
+        };
+        self.dispatch(name, &bindings, &plan).await
+    }
+
+    /// Copies the contents of the array's storage buffers back to the host.
+    pub async fn read<T: Pod>(&self, id: &String) -> Result<Vec<T>, Error> {
+        let (size, _) = self.chunks(id)?;
+        let whole = 0..size as usize / std::mem::size_of::<T>();
+
+        self.read_runs(id, &[whole]).await
+    }
+
+    /// Copies the elements in `runs` back to the host, one run after the other. Only the bytes of the runs are copied.
+    /// The data goes through the shared staging buffers, as many runs at a time as fit into the staging budget.
+    pub async fn read_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>]) -> Result<Vec<T>, Error> {
+        let adapter = self.handle()?;
+        // The handles are cloned so the lock isn't held while we wait on the GPU.
+        let (_, chunks) = self.chunks(id)?;
+        let element = std::mem::size_of::<T>() as u64;
+        let budget = self.staging.budget();
+
+        // Every part of a run within a single chunk, split further where it wouldn't fit into a staging buffer.
+        let mut copies = Vec::new();
+        for run in runs {
+            for (chunk, offset, len) in pieces(&chunks, run.start as u64 * element..run.end as u64 * element) {
+                let mut done = 0;
+                while done < len {
+                    let n = (len - done).min(budget);
+                    copies.push((chunk, offset + done, n));
+                    done += n;
+                }
+            }
+        }
+
+        let mut result = vec![T::zeroed(); runs.iter().map(|run| run.len()).sum()];
+        let bytes = bytemuck::cast_slice_mut::<T, u8>(&mut result);
+        let (mut rest, mut start) = (copies.as_slice(), 0);
+        while !rest.is_empty() {
+            let (mut count, mut fill) = (0, 0);
+            while count < rest.len() && fill + rest[count].2 <= budget {
+                fill += rest[count].2;
+                count += 1;
+            }
+            let (batch, remaining) = rest.split_at(count);
+            rest = remaining;
+
+            let staging = self.staging_buffer(adapter, fill).await?;
+            let output = &mut bytes[start..start + fill as usize];
+            self.read_staged(adapter, batch, &staging.buffer, output).await?;
+            // Only handed back once unmapped. If the read is abandoned halfway, the buffer is simply freed.
+            self.staging.give(staging);
+            start += fill as usize;
+        }
+
+        Ok(result)
+    }
+
+    /// Overwrites the elements in `runs` with `data`, which holds the new contents of one run after the other.
+    pub async fn write_runs<T: Pod>(&self, id: &String, runs: &[Range<usize>], data: &[T]) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let (_, chunks) = self.chunks(id)?;
+        let element = std::mem::size_of::<T>() as u64;
+        let contents = bytemuck::cast_slice::<T, u8>(data);
+
+        // The queue applies writes before any commands submitted after them, including those an open batch is still
+        // holding back, so those are submitted first to keep the order they were issued in.
+        adapter.scoped(|_| {
+            self.flush(adapter);
+            let mut start = 0;
+            for run in runs {
+                for (chunk, offset, len) in pieces(&chunks, run.start as u64 * element..run.end as u64 * element) {
+                    adapter.queue.write_buffer(chunk, offset, &contents[start..start + len as usize]);
+                    start += len as usize;
+                }
+            }
+        }).await
+    }
+
+    /// Test function.
+    /// Doubles the array input
+    pub async fn execute_op(&self, id: &String, operation: Operation) -> Result<Vec<u32>, Error> {
+        let size = {
+            let buffers = self.buffers.read().unwrap();
+            let Some(buffer) = buffers.get(id) else {
+                return Err(Error::UnknownArray(id.clone()));
+            };
+            buffer.size
+        };
+        // The kernel doubles the buffer in place, so it has no inputs besides its output.
+        let len = size as usize / std::mem::size_of::<u32>();
+        self.launch(&Launch {
+            operation,
+            output: id,
+            inputs: &[],
+            params: [len as u32, 0, 0, 0],
+            len,
+        }).await?;
+
+        self.read(id).await
+    }
+}
+
+// Batching
+impl GpuBackend {
+    /// Starts holding back commands until the batch is ended.
+    pub fn begin_batch(&self) {
+        self.recorder.lock().unwrap().batches += 1;
+    }
+
+    /// Ends a batch. Once no batch is open anymore, everything recorded is submitted in a single command buffer.
+    pub fn end_batch(&self) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        let mut recorder = self.recorder.lock().unwrap();
+        recorder.batches = recorder.batches.saturating_sub(1);
+        if recorder.batches == 0 {
+            self.submit(adapter, &mut recorder);
+        }
+
+        Ok(())
+    }
+
+    /// Ends a batch like [GpuBackend::end_batch], then waits until the device has finished all submitted work.
+    /// Errors in the recorded commands are reported here, since they only surface once the commands are submitted.
+    pub async fn finish_batch(&self) -> Result<(), Error> {
+        let adapter = self.handle()?;
+        adapter.scoped(|_| self.end_batch()).await??;
+
+        adapter.wait_idle().await
+    }
+}
+
+// Private impl
+impl GpuBackend {
+    /// Records commands with `f`. They're submitted right away, unless a batch is open and holds them back.
+    fn record(&self, adapter: &GpuHandle, f: impl FnOnce(&mut CommandEncoder)) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.batches == 0 {
+            let mut encoder = adapter.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            f(&mut encoder);
+            adapter.queue.submit(Some(encoder.finish()));
+            return;
+        }
+
+        let encoder = recorder.encoder.get_or_insert_with(|| {
+            adapter.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Batch") })
+        });
+        f(encoder);
+    }
+
+    /// Submits whatever an open batch has recorded so far, so later commands see its results.
+    fn flush(&self, adapter: &GpuHandle) {
+        self.submit(adapter, &mut self.recorder.lock().unwrap());
+    }
+
+    /// Submits the commands `recorder` holds back, after which the buffers retired meanwhile can be pooled.
+    fn submit(&self, adapter: &GpuHandle, recorder: &mut Recorder) {
+        if let Some(encoder) = recorder.encoder.take() {
+            adapter.queue.submit(Some(encoder.finish()));
+        }
+        for mut buffers in recorder.retired.drain(..) {
+            buffers.allocation.move_to(Category::Pool);
+            self.pool.give(buffers.capacity(), buffers);
+        }
+    }
+
+    /// Sizes of the chunks an array of `size` bytes is stored in: as many chunks of the largest bindable size as it
+    /// fills, then one for the rest, rounded up to its pool bucket. Sizes of the same bucket always get the same layout.
+    fn layout(&self, size: u64) -> Vec<u64> {
+        let full = size.saturating_sub(1) / self.chunk_size;
+        let mut sizes = vec![self.chunk_size; full as usize];
+        sizes.push(pool::bucket(size - full * self.chunk_size).min(self.chunk_size));
+
+        sizes
+    }
+
+    /// Takes buffers for `size` bytes from the pool, or creates them if it has none of their bucket.
+    /// Also returns whether they were recycled, in which case the storage buffers still hold the data of their last array.
+    async fn acquire(&self, adapter: &GpuHandle, size: u64) -> Result<(Buffers, bool), Error> {
+        let layout = self.layout(size);
+        let bucket = layout.iter().sum();
+        if let Some(mut buffers) = self.pool.take(bucket) {
+            buffers.size = size;
+            buffers.allocation.move_to(Category::Storage);
+            return Ok((buffers, true));
+        }
+
+        let allocation = self.reserve(Category::Storage, bucket)?;
+        let buffers = adapter.scoped(|device| {
+            // Usage allowing the buffer to be:
+            //   A storage buffer (can be bound within a bind group and thus available to a shader).
+            //   The destination of a copy.
+            //   The source of a copy.
+            let chunks = layout
+                .iter()
+                .map(|&chunk_size| {
+                    device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Storage Buffer"),
+                        size: chunk_size,
+                        usage: BufferUsages::STORAGE
+                            | BufferUsages::COPY_DST
+                            | BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    })
+                })
+                .collect();
+            Buffers {
+                size,
+                chunks,
+                allocation,
+            }
+        }).await?;
+
+        Ok((buffers, false))
+    }
+
+    /// Accounts for `bytes` about to be allocated. If they don't fit under the memory limit, the idle buffers
+    /// are freed to make room before giving up.
+    fn reserve(&self, category: Category, bytes: u64) -> Result<Allocation, Error> {
+        self.memory.reserve(category, bytes).or_else(|_| {
+            self.empty_cache();
+            self.memory.reserve(category, bytes)
+        })
+    }
+
+    /// Returns the [GpuHandle], failing if there is none or the device has been lost.
+    fn handle(&self) -> Result<&GpuHandle, Error> {
+        let Some(adapter) = self.adapter.as_deref() else {
+            return Err(Error::NoAdapter);
+        };
+        adapter.check_lost()?;
+
+        Ok(adapter)
+    }
+
+    /// Takes a staging buffer for `len` bytes from the ring, or creates one.
+    async fn staging_buffer(&self, adapter: &GpuHandle, len: u64) -> Result<StagingBuffer, Error> {
+        if let Some(staging) = self.staging.take(len) {
+            return Ok(staging);
+        }
+
+        let size = self.staging.size_for(len);
+        let allocation = self.reserve(Category::Staging, size)?;
+        let buffer = adapter.scoped(|device| {
+            // `usage` of buffer specifies how it can be used:
+            //   `BufferUsages::MAP_READ` allows it to be read (outside the shader).
+            //   `BufferUsages::COPY_DST` allows it to be the destination of the copy.
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Staging Buffer"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        }).await?;
+
+        Ok(StagingBuffer { buffer, allocation })
+    }
+
+    /// Runs the element-wise kernel `name` over the first `len` elements of `output`, one dispatch per chunk.
+    /// Each binds the chunk of `output` and the matching chunks of the `inputs`; an input in a single chunk is bound
+    /// whole to every dispatch. `params` gives the words of each uniform from the index of the dispatch's first element
+    /// and the number of elements it covers.
+    async fn dispatch_chunks(
+        &self,
+        name: &str,
+        output: &String,
+        inputs: &[Vec<Buffer>],
+        len: usize,
+        params: impl Fn(usize, usize) -> Vec<u32>,
+    ) -> Result<(), Error> {
+        let (_, chunks) = self.chunks(output)?;
+        if let Some(input) = inputs.iter().find(|input| input.len() != 1 && input.len() != chunks.len()) {
+            return Err(Error::InvalidArgument(format!(
+                "an input of {} spans {} buffers, but the output spans {}",
+                name,
+                input.len(),
+                chunks.len()
+            )));
+        }
+        let limits = self.handle()?.device.limits();
+        let size = std::mem::size_of::<u32>() as u64;
+
+        for (c, (chunk, span)) in spans(&chunks, len as u64 * size).enumerate() {
+            let (first, count) = ((span.start / size) as usize, ((span.end - span.start) / size) as usize);
+            let uniform = self.create_uniform(&params(first, count))?;
+
+            let mut bindings = vec![Binding::Storage(chunk)];
+            bindings.extend(inputs.iter().map(|input| Binding::ReadOnly(&input[c.min(input.len() - 1)])));
+            bindings.push(Binding::Uniform(&uniform));
+
+            let plan = DispatchPlan::new(count, &limits)?;
+            self.dispatch(name, &bindings, &plan).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the `(buffer, offset, len)` ranges in `copies` into `output` through `staging_buffer`, packed one
+    /// after the other. They're all copied in one submission, then mapped together.
+    async fn read_staged(&self, adapter: &GpuHandle, copies: &[(&Buffer, u64, u64)], staging_buffer: &Buffer, output: &mut [u8]) -> Result<(), Error> {
+        let len = output.len() as u64;
+        // Copies the data from the storage buffers on the GPU into the staging buffer, then submits the copies along
+        // with anything an open batch is still holding back, since the copy has to see the results.
+        adapter.scoped(|_| {
+            self.record(adapter, |encoder| {
+                let mut destination = 0;
+                for &(source, offset, len) in copies {
+                    encoder.copy_buffer_to_buffer(source, offset, staging_buffer, destination, len);
+                    destination += len;
+                }
+            });
+            self.flush(adapter);
+        }).await?;
+
+        // Note that we're not calling `.await` here.
+        let buffer_slice = staging_buffer.slice(..len);
+        // Sets the buffer up for mapping, sending over the result of the mapping back to us when it is finished.
+        let (sender, receiver) = flume::bounded(1);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| {
+            let _ = sender.send(v);
+        });
+
+        // The poller thread drives the device, so waiting here leaves the runtime free for other tasks.
+        adapter.poller.wake();
+
+        // Awaits until `buffer_future` can be read from
+        match receiver.recv_async().await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(Error::MapFailed(e.to_string())),
+            Err(_) => return Err(Error::MapFailed("the map callback was dropped without being called".into())),
+        }
+        adapter.check_lost()?;
+
+        // Gets contents of buffer. The mapped view is a temporary, so it's dropped before the buffer is unmapped,
+        // as the current interface requires.
+        output.copy_from_slice(&buffer_slice.get_mapped_range());
+        staging_buffer.unmap(); // Unmaps buffer from memory
+
+        Ok(())
+    }
+
+    /// Get device description. Returns the adapter selected by `options` (by default the highest performance device on a system). Should only be called once unless you need to request another adapter.
+    async fn get_adapter_info(options: &ContextBuilder) -> Result<GpuHandle, Error> {
+        let adapter = select_adapter(options).await?;
+
+        debug!("Adapter(s) = {:?}", adapter.get_info());
+
+        // Persisting pipelines needs a device level feature, which is only asked for when a cache directory is set.
+        let mut required_features = options.features;
+        if options.pipeline_cache_directory.is_some() && adapter.features().contains(Features::PIPELINE_CACHE) {
+            required_features |= Features::PIPELINE_CACHE;
+        }
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Device 1"),                // Debug label
+                    required_features, // Define a list of features that the device must implement.
+                    required_limits: options.limits.clone().unwrap_or_else(|| adapter.limits()), // Defines a list of limits of certain types of resources that we can create.
+                    memory_hints: MemoryHints::MemoryUsage, // Defines memory allocation hints for our device.
+                },
+                None, // Typically a path used for tracing api calls.
+            )
+            .await
+            .map_err(|e| Error::DeviceRequest(e.to_string()))?;
+
+        GpuHandle::new(adapter.get_info(), device, queue)
+    }
+}
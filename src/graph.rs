@@ -0,0 +1,206 @@
+use crate::dtype::DType;
+use crate::error::Error;
+use crate::execution::Executor;
+use crate::map::{self, MapKernel, Value, MAX_MAP_INPUTS};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Most element-wise operations fused into one kernel. Longer chains are split by materializing an intermediate.
+pub const MAX_FUSED_STEPS: usize = 32;
+
+/// The device buffers of a materialized [crate::Array].
+/// They're freed once the array and every pending expression reading them are gone.
+#[derive(Debug)]
+pub struct Storage {
+    pub id: String,
+    executor: Arc<Executor>,
+}
+
+impl Storage {
+    /// Takes ownership of the buffers registered under `id`.
+    pub fn new(executor: &Arc<Executor>, id: String) -> Self {
+        Storage {
+            id,
+            executor: executor.clone(),
+        }
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        Executor::drop(&self.executor, &self.id);
+    }
+}
+
+/// The contents of an [crate::Array]: either buffers on the device or an element-wise expression that hasn't run yet.
+#[derive(Debug, Clone)]
+pub enum Node {
+    Buffer(Arc<Storage>),
+    Map(Arc<MapNode>),
+}
+
+/// A map expression applied to other nodes, one per argument of the kernel.
+#[derive(Debug)]
+pub struct MapNode {
+    pub kernel: Arc<MapKernel>,
+    pub operands: Vec<Node>,
+}
+
+impl Node {
+    pub fn map(kernel: Arc<MapKernel>, operands: Vec<Node>) -> Self {
+        Node::Map(Arc::new(MapNode { kernel, operands }))
+    }
+
+    /// Whether the whole expression still fits into a single fused kernel.
+    pub fn fusible(&self) -> bool {
+        let mut plan = Plan::default();
+        plan.visit(self);
+
+        plan.inputs.len() <= MAX_MAP_INPUTS && plan.steps.len() <= MAX_FUSED_STEPS
+    }
+}
+
+/// Where a step of a fused kernel takes an argument from.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Input(usize), // The element of an input buffer.
+    Step(usize),  // The result of an earlier step.
+}
+
+#[derive(Debug)]
+struct Step {
+    kernel: Arc<MapKernel>,
+    operands: Vec<Operand>,
+}
+
+/// Every map node of an expression in evaluation order, with its distinct input buffers.
+/// Nodes reachable along several paths are only evaluated once.
+#[derive(Default)]
+struct Plan {
+    inputs: Vec<Arc<Storage>>,
+    steps: Vec<Step>,
+    seen: HashMap<*const MapNode, usize>,
+}
+
+impl Plan {
+    fn visit(&mut self, node: &Node) -> Operand {
+        match node {
+            Node::Buffer(storage) => match self.inputs.iter().position(|input| input.id == storage.id) {
+                Some(index) => Operand::Input(index),
+                None => {
+                    self.inputs.push(storage.clone());
+                    Operand::Input(self.inputs.len() - 1)
+                }
+            },
+            Node::Map(map) => {
+                if let Some(&step) = self.seen.get(&Arc::as_ptr(map)) {
+                    return Operand::Step(step);
+                }
+                let operands = map.operands.iter().map(|operand| self.visit(operand)).collect();
+                self.steps.push(Step {
+                    kernel: map.kernel.clone(),
+                    operands,
+                });
+                self.seen.insert(Arc::as_ptr(map), self.steps.len() - 1);
+                Operand::Step(self.steps.len() - 1)
+            }
+        }
+    }
+}
+
+/// A kernel evaluating a whole expression of map nodes in one pass, so none of the intermediates is ever stored.
+#[derive(Debug)]
+pub struct FusedKernel {
+    pub name: String,   // Derived from the source, so the same expression reuses its shader module and pipeline.
+    pub source: String, // Built from the sources of the individual maps, which naga has validated already.
+    pub dtype: DType,
+    steps: Vec<Step>,
+}
+
+impl FusedKernel {
+    /// Plans the kernel for `node`, which must be a [Node::Map], returning it with the buffers to bind as its inputs, in order.
+    pub fn new(dtype: DType, node: &Node) -> (FusedKernel, Vec<Arc<Storage>>) {
+        let mut plan = Plan::default();
+        let result = plan.visit(node);
+        let source = generate(dtype, &plan, result);
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+
+        let kernel = FusedKernel {
+            name: format!("map_{:016x}", hasher.finish()),
+            source,
+            dtype,
+            steps: plan.steps,
+        };
+        (kernel, plan.inputs)
+    }
+
+    /// Evaluates the expression for one element, given the element of every input and the [FusedKernel::programs].
+    pub(crate) fn eval(&self, programs: &[&map::Expr], inputs: &[Value]) -> Value {
+        let mut results = [Value::U32(0); MAX_FUSED_STEPS];
+        for (i, (step, program)) in self.steps.iter().zip(programs).enumerate() {
+            let mut arguments = [Value::U32(0); MAX_MAP_INPUTS];
+            for (argument, operand) in arguments.iter_mut().zip(&step.operands) {
+                *argument = match *operand {
+                    Operand::Input(index) => inputs[index],
+                    Operand::Step(index) => results[index],
+                };
+            }
+            results[i] = program.eval(&arguments[..step.operands.len()]);
+        }
+
+        results[self.steps.len() - 1]
+    }
+
+    /// The programs of every step as the CPU backend evaluates them, for [FusedKernel::eval].
+    /// Fails with [Error::Unsupported] if a step uses WGSL the CPU evaluator doesn't implement.
+    pub(crate) fn programs(&self) -> Result<Vec<&map::Expr>, Error> {
+        self.steps.iter().map(|step| step.kernel.program()).collect()
+    }
+}
+
+/// Builds the source of a fused kernel: one function per distinct map expression, called in order by `expression`.
+fn generate(dtype: DType, plan: &Plan, result: Operand) -> String {
+    let ty = dtype.wgsl_name();
+    let operand = |operand: Operand| match operand {
+        Operand::Input(index) => format!("luma_input_{}", index),
+        Operand::Step(index) => format!("luma_step_{}", index),
+    };
+
+    let mut source = map::header(plan.inputs.len());
+    let mut functions = HashMap::new();
+    for step in &plan.steps {
+        let kernel = Arc::as_ptr(&step.kernel);
+        if functions.contains_key(&kernel) {
+            continue;
+        }
+        let parameters = step.kernel.arguments.iter().map(|name| format!("{}: {}", name, ty)).collect::<Vec<_>>();
+        source += &format!(
+            // The expression gets a line of its own, so a trailing comment can't swallow the `;`.
+            "fn luma_map_{}({}) -> {} {{\n    return\n        {}\n    ;\n}}\n\n",
+            functions.len(),
+            parameters.join(", "),
+            ty,
+            step.kernel.expression
+        );
+        functions.insert(kernel, functions.len());
+    }
+
+    let parameters = (0..plan.inputs.len()).map(|i| format!("luma_input_{}: {}", i, ty)).collect::<Vec<_>>();
+    source += &format!("fn expression({}) -> {} {{\n", parameters.join(", "), ty);
+    for (i, step) in plan.steps.iter().enumerate() {
+        let arguments = step.operands.iter().map(|&o| operand(o)).collect::<Vec<_>>();
+        source += &format!(
+            "    let luma_step_{} = luma_map_{}({});\n",
+            i,
+            functions[&Arc::as_ptr(&step.kernel)],
+            arguments.join(", ")
+        );
+    }
+    source += &format!("    return {};\n}}\n\n", operand(result));
+    source += &map::entry_point(dtype, plan.inputs.len());
+
+    source
+}
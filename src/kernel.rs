@@ -0,0 +1,97 @@
+use crate::error::Error;
+use std::path::Path;
+
+/// A user supplied WGSL compute kernel, registered with [crate::Context::register_kernel] and run with
+/// [crate::Context::run_kernel].
+///
+/// The kernel's entry point must be called `main`. Its bindings all live in group 0, in this order:
+/// the inputs as `var<storage, read>`, then the outputs as `var<storage, read_write>`, then, if the kernel
+/// declares one, a `var<uniform>` holding the parameters. Elements are raw 32 bit words, like in the built-in kernels.
+///
+/// # Example
+/// ```no_run
+/// # async fn example(ctx: luma::Context, a: luma::Array, mut out: luma::Array) -> Result<(), luma::Error> {
+/// let source = r#"
+///     override WORKGROUP_SIZE: u32 = 64;
+///     @group(0) @binding(0) var<storage, read> input: array<f32>;
+///     @group(0) @binding(1) var<storage, read_write> output: array<f32>;
+///     @group(0) @binding(2) var<uniform> len: vec4<u32>;
+///
+///     @compute @workgroup_size(WORKGROUP_SIZE)
+///     fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+///         if (id.x < len.x) {
+///             output[id.x] = sqrt(input[id.x]);
+///         }
+///     }
+/// "#;
+/// ctx.register_kernel(luma::Kernel::new("sqrt", source).inputs(1).outputs(1).uniform(true)).await?;
+/// ctx.run_kernel("sqrt", &[&a], &mut [&mut out], &[a.len() as u32], luma::Grid::Elements(a.len())).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Kernel {
+    pub(crate) name: String,
+    pub(crate) source: String,
+    pub(crate) inputs: usize,
+    pub(crate) outputs: usize,
+    pub(crate) uniform: bool,
+}
+
+impl Kernel {
+    /// A kernel with one output, no inputs and no parameters until declared otherwise.
+    pub fn new(name: &str, source: &str) -> Self {
+        Kernel {
+            name: name.to_string(),
+            source: source.to_string(),
+            inputs: 0,
+            outputs: 1,
+            uniform: false,
+        }
+    }
+
+    /// Reads the kernel's source from a `.wgsl` file.
+    pub fn from_file(name: &str, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| Error::ShaderCompile {
+            shader: path.display().to_string(),
+            message: format!("Could not read file contents: {}", e),
+        })?;
+
+        Ok(Kernel::new(name, &source))
+    }
+
+    /// Number of read-only input arrays, bound first.
+    pub fn inputs(mut self, inputs: usize) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Number of output arrays, bound after the inputs.
+    pub fn outputs(mut self, outputs: usize) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    /// Whether the kernel takes its parameters in a uniform buffer, bound last.
+    pub fn uniform(mut self, uniform: bool) -> Self {
+        self.uniform = uniform;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// How many invocations of a custom kernel to launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grid {
+    /// At least one invocation per element, planned like the built-in kernels. The kernel has to declare
+    /// `override WORKGROUP_SIZE: u32;` and use it as its workgroup size, and must skip indices past its length.
+    /// Large counts spread the workgroups over y and z as well, so the kernel has to flatten the grid
+    /// with `num_workgroups` to handle them.
+    Elements(usize),
+    /// Exactly this many workgroups along x, y and z, with whatever workgroup size the kernel fixes itself.
+    Workgroups([u32; 3]),
+}
@@ -1,95 +1,531 @@
 #![allow(dead_code)]
 extern crate core;
-use std::sync::OnceLock;
-use bytemuck::Pod;
-use uuid::Uuid;
+mod adapter;
+mod batch;
+pub mod blocking;
+mod context;
+mod cpu;
+mod dispatch;
+mod dtype;
+mod error;
 mod execution;
+mod graph;
+mod kernel;
+mod lru;
+mod map;
+mod memory;
+mod gpu;
+mod pipeline;
+mod poller;
+mod pool;
+mod shaders;
+mod staging;
+mod turns;
 mod utils;
 
-use crate::execution::{Executor, Operation};
+pub use crate::adapter::{adapters, AdapterDescription, AdapterSelector, ADAPTER_ENV};
+pub use crate::batch::CommandBatch;
+pub use crate::context::{Context, ContextBuilder};
+pub use crate::dtype::{DType, Element};
+pub use crate::error::Error;
+pub use crate::execution::{BackendKind, BACKEND_ENV};
+pub use crate::kernel::{Grid, Kernel};
+pub use crate::map::CacheStats;
+pub use crate::memory::MemoryStats;
+pub use crate::pool::PoolStats;
+pub use crate::utils::Nested;
+/// # Example
+/// ```no_run
+/// #[luma::kernel]
+/// fn saxpy(a: f32, x: &[f32], y: &mut [f32]) {
+///     let i = index();
+///     y[i] = a.mul_add(x[i], y[i]);
+/// }
+///
+/// # async fn example(x: luma::Array, mut y: luma::Array) -> Result<(), luma::Error> {
+/// saxpy(2.0, &x, &mut y).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub use luma_macros::kernel;
+use crate::execution::Operation;
+use crate::graph::{Node, Storage};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+pub use wgpu::{AdapterInfo, Backend, Backends, DeviceType, Features, Limits, PowerPreference};
 
 /// Instantiates a new [Array]
-/// The first argument is the dimensions of the array, while the second is the data to initialize it
-/// with.
+/// Either takes nested literal data and infers the dimensions from it, or takes the dimensions of the
+/// array as the first argument and the flat data to initialize it with as the second.
+/// It awaits the new array, so it only works inside async code; [blocking::array!] works anywhere.
 ///
 /// # Example
-/// ```
-///  let array1 = luma::array!(&[3u64, 1, 1, 1], &[1u32, 6u32, 5u32]);
+/// ```no_run
+/// # async fn example() {
+///  let array1 = luma::array!(&[3, 1, 1, 1], &[1u32, 6u32, 5u32]);
+///  let matrix = luma::array![[1.0, 2.0], [3.0, 4.0]]; // dimensions [2, 2, 1, 1]
+///  let vector = luma::array![1, 6, 5]; // dimensions [3, 1, 1, 1]
+/// # }
 /// ```
 #[macro_export]
 macro_rules! array {
+    ($([$($row:tt)*]),+ $(,)?) => {
+        $crate::Array::from_nested([$([$($row)*]),+])
+        .await.expect("Could not create Array.")
+    };
+    ($($x:literal),+ $(,)?) => {
+        $crate::Array::from_nested([$($x),+])
+        .await.expect("Could not create Array.")
+    };
     ($dims:expr, $data:expr) => {
-        Array::new($dims, $data)
-        .await.expect("Could not create Array.");
+        $crate::Array::new($dims, $data)
+        .await.expect("Could not create Array.")
     };
 }
 
-const PROJECT_DIR: &str = env!("CARGO_MANIFEST_DIR");
-const SHADERS_PATH: &str = "./operations";
-
-/// Static thread-safe executor with interior mutability.
-static EXECUTOR: OnceLock<Executor> = OnceLock::new();
+/// Blocking version of [array!], creating a [blocking::Array] outside of async code.
+/// Use it as `luma::blocking::array!`.
+///
+/// # Example
+/// ```no_run
+/// let matrix = luma::blocking::array![[1.0, 2.0], [3.0, 4.0]];
+/// let doubled = matrix.map("x * 2.0").unwrap();
+/// ```
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __array_blocking {
+    ($([$($row:tt)*]),+ $(,)?) => {
+        $crate::blocking::Array::from_nested([$([$($row)*]),+])
+        .expect("Could not create Array.")
+    };
+    ($($x:literal),+ $(,)?) => {
+        $crate::blocking::Array::from_nested([$($x),+])
+        .expect("Could not create Array.")
+    };
+    ($dims:expr, $data:expr) => {
+        $crate::blocking::Array::new($dims, $data)
+        .expect("Could not create Array.")
+    };
+}
 
 /// Instantiates a new [Array]
 /// The first argument is the dimensions of the array, while the second is the data to initialize it
 /// with.
 ///
+/// Arrays made through the associated constructors live on the global [Context]; use the methods on
+/// a [Context] to place them on a specific device instead.
+///
+/// Element-wise operations are lazy: they return at once with an array that remembers how to compute its contents.
+/// Nothing runs until it is read or [Array::eval]ed, and then the whole chain of pending operations runs as one
+/// fused kernel, without storing any of the intermediate results.
+///
 /// # Example
-/// ```
-/// async {
+/// ```no_run
+/// # async fn example() {
 ///     let array1 = luma::Array::new(&[3, 1, 1, 1], &[1u32, 6u32, 5u32]).await.expect("Could not create Array.");
-/// }
+/// # }
 /// ```
 #[derive(Debug)]
 pub struct Array {
+    context: Context,
     dimensions: [usize; 4],
+    dtype: DType,
     id: String,
+    node: Mutex<Node>, // The buffers holding the contents, or the expression computing them.
 }
 
-impl Drop for Array {
-    /// We need to handle when it goes out of scope by deleting it from our [Executor]
-    fn drop(&mut self) {
-        EXECUTOR.get().expect("Could not drop value").drop(&self.id);
+/// Evaluates the WGSL expression `expression` element-wise over several arrays, which it refers to as `a`, `b`, `c`
+/// and so on, in order. At most seven arrays can be combined; they must all have the same dtype and
+/// dimensions and live on the same [Context]. See [Array::map] for how expressions are checked.
+///
+/// # Example
+/// ```no_run
+/// # async fn example(a: luma::Array, b: luma::Array) -> Result<(), luma::Error> {
+/// let c = luma::zip_map(&[&a, &b], "a * b + 1.0").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn zip_map(inputs: &[&Array], expression: &str) -> Result<Array, Error> {
+    const NAMES: [&str; map::MAX_MAP_INPUTS] = ["a", "b", "c", "d", "e", "f", "g"];
+    if inputs.len() > NAMES.len() {
+        return Err(Error::InvalidArgument(format!(
+            "zip_map combines at most {} arrays, but {} were given",
+            NAMES.len(),
+            inputs.len()
+        )));
     }
+    let Some(first) = inputs.first() else {
+        return Err(Error::InvalidArgument("zip_map needs at least one input".into()));
+    };
+
+    first.context.map(inputs, &NAMES[..inputs.len()], expression).await
 }
 
 impl Array {
-    pub async fn new<T>(dimensions: &[usize; 4], data: &[T]) -> Result<Self, String>
+    /// Creates an [Array] of the given dimensions from flat, row-major data.
+    /// The product of `dimensions` must equal `data.len()`; any zero dimension makes an empty array.
+    ///
+    /// # Example
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let error = luma::Array::new(&[2, 2, 1, 1], &[1u32, 2, 3]).await.unwrap_err();
+    /// assert_eq!(error, luma::Error::ShapeMismatch { dimensions: [2, 2, 1, 1], expected: 4, actual: 3 });
+    /// # });
+    /// ```
+    pub async fn new<T>(dimensions: &[usize; 4], data: &[T]) -> Result<Self, Error>
     where
-        T: Pod + std::fmt::Debug,
+        T: Element,
     {
-        // Set up the executor only if not already initialized.
-        std::thread::spawn(|| {
-            Box::pin(
-                async {
-                    if EXECUTOR.get().is_none() {
-                        let ex = Executor::new(&format!("{}/{}", PROJECT_DIR, SHADERS_PATH)).await.unwrap();
-                        EXECUTOR.set(ex).unwrap();
-                    }
-                }
-            )
-        }).join().unwrap().await;
-
-        // let test = vec![vec![3, 5, 6], vec![1, 2, 3], vec![2, 3, 6]];
-        // println!("Dimensions: {:?}", utils::extrapolate_dimensions(&test));
-
-        let id = Uuid::new_v4();
-        // Setup input output buffers with our data
-        // TODO: Incorporate the dimensions array
-        EXECUTOR.get().unwrap().setup_buffers(dimensions, data, id.into()).await?;
-
-        Ok(Array {
-            dimensions: *dimensions,
-            id: id.into()
-        })
+        // Bad input is reported before the global context gets set up.
+        utils::check_shape(dimensions, data.len())?;
+
+        Context::global().await?.array(dimensions, data).await
+    }
+
+    /// Creates an [Array] from nested data, inferring the dimensions from the nesting.
+    /// Ragged data, where rows of the same level differ in length, is rejected.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() {
+    /// let matrix = luma::Array::from_nested(vec![vec![1u32, 2, 3], vec![4, 5, 6]]).await.unwrap();
+    /// assert_eq!(matrix.dimensions(), [2, 3, 1, 1]);
+    /// # }
+    /// ```
+    pub async fn from_nested<N: Nested>(data: N) -> Result<Self, Error> {
+        let (dimensions, flat) = utils::flatten(data)?;
+
+        Array::new(&dimensions, &flat).await
+    }
+
+    /// Creates an [Array] filled with zeros.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() {
+    /// let zeros = luma::Array::zeros::<f32>(&[2, 3, 1, 1]).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn zeros<T: Element>(dimensions: &[usize; 4]) -> Result<Self, Error> {
+        Context::global().await?.zeros::<T>(dimensions).await
+    }
+
+    /// Creates an [Array] filled with ones.
+    pub async fn ones<T: Element>(dimensions: &[usize; 4]) -> Result<Self, Error> {
+        Context::global().await?.ones::<T>(dimensions).await
+    }
+
+    /// Creates an [Array] with every element set to `value`.
+    pub async fn full<T: Element>(dimensions: &[usize; 4], value: T) -> Result<Self, Error> {
+        Context::global().await?.full(dimensions, value).await
+    }
+
+    /// Creates an [Array] of zeros with the same dimensions, [DType] and [Context] as `other`.
+    pub async fn zeros_like(other: &Array) -> Result<Self, Error> {
+        other.context.zeros_of(&other.dimensions, other.dtype).await
+    }
+
+    /// Creates an [Array] of ones with the same dimensions, [DType] and [Context] as `other`.
+    pub async fn ones_like(other: &Array) -> Result<Self, Error> {
+        other.context.full_of(&other.dimensions, other.dtype, other.dtype.one_bits()).await
+    }
+
+    /// Creates a one dimensional [Array] holding `start, start + step, ...` up to but excluding `stop`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() {
+    /// // [0, 2, 4, 6, 8]
+    /// let evens = luma::Array::arange(0u32, 10, 2).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn arange<T: Element>(start: T, stop: T, step: T) -> Result<Self, Error> {
+        Context::global().await?.arange(start, stop, step).await
+    }
+
+    /// Creates a one dimensional [Array] of `num` evenly spaced values from `start` to `stop`, inclusive.
+    pub async fn linspace(start: f32, stop: f32, num: usize) -> Result<Self, Error> {
+        Context::global().await?.linspace(start, stop, num).await
+    }
+
+    /// Creates an `n` by `n` identity matrix.
+    pub async fn eye<T: Element>(n: usize) -> Result<Self, Error> {
+        Context::global().await?.eye::<T>(n).await
+    }
+
+    /// Creates coordinate matrices from the one dimensional arrays `x` and `y`.
+    /// Both results have `y.len()` rows and `x.len()` columns; the first repeats `x` along every row
+    /// and the second repeats `y` along every column.
+    pub async fn meshgrid(x: &Array, y: &Array) -> Result<(Self, Self), Error> {
+        if x.dtype != y.dtype {
+            return Err(Error::DtypeMismatch { expected: x.dtype, actual: y.dtype });
+        }
+        if !x.context.same_device(&y.context) {
+            return Err(Error::InvalidArgument("meshgrid inputs live on different contexts".into()));
+        }
+        for input in [x, y] {
+            if input.dimensions[1..].iter().any(|&dimension| dimension != 1) {
+                return Err(Error::InvalidArgument(format!(
+                    "meshgrid takes one dimensional arrays, but got one with dimensions {:?}",
+                    input.dimensions
+                )));
+            }
+        }
+        let (nx, ny) = (x.len(), y.len());
+        let dimensions = [ny, nx, 1, 1];
+
+        let xs = x.context.tile(x, &dimensions, 1, nx).await?;
+        let ys = x.context.tile(y, &dimensions, nx, ny).await?;
+
+        Ok((xs, ys))
+    }
+
+    /// Element-wise `self + other`. Both arrays must have the same dtype and dimensions and live on the same [Context].
+    /// Integers wrap on overflow.
+    pub async fn add(&self, other: &Array) -> Result<Array, Error> {
+        self.context.binary("a + b", self, other).await
+    }
+
+    /// Element-wise `self - other`. Integers wrap on overflow.
+    pub async fn subtract(&self, other: &Array) -> Result<Array, Error> {
+        self.context.binary("a - b", self, other).await
+    }
+
+    /// Element-wise `self * other`. Integers wrap on overflow.
+    pub async fn multiply(&self, other: &Array) -> Result<Array, Error> {
+        self.context.binary("a * b", self, other).await
+    }
+
+    /// Element-wise `self / other`. Integer division by zero yields the element of `self`, as in WGSL.
+    pub async fn divide(&self, other: &Array) -> Result<Array, Error> {
+        self.context.binary("a / b", self, other).await
+    }
+
+    /// Evaluates the WGSL expression `expression` for every element, which it refers to as `x`.
+    /// The expression has to produce the array's own dtype. It's validated before anything runs, so mistakes come
+    /// back as [Error::ShaderCompile] with the column they're at. Each distinct expression is compiled once and cached.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() -> Result<(), luma::Error> {
+    /// let x = luma::Array::linspace(0.0, 1.0, 5).await?;
+    /// let y = x.map("x * 2.0 + sin(x)").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn map(&self, expression: &str) -> Result<Array, Error> {
+        self.context.map(&[self], &["x"], expression).await
+    }
+
+    /// Computes the contents of the array now, if they're still pending, instead of when they're first read.
+    /// Chains of element-wise operations are evaluated in one fused kernel either way.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(a: luma::Array, b: luma::Array) -> Result<(), luma::Error> {
+    /// let c = a.add(&b).await?.map("x * x").await?; // Nothing has run yet.
+    /// c.eval().await?; // Runs a single kernel computing `(a + b) * (a + b)`.
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn eval(&self) -> Result<(), Error> {
+        self.storage().await.map(|_| ())
+    }
+
+    /// Whether the contents have been computed, rather than still waiting on pending operations.
+    pub fn is_evaluated(&self) -> bool {
+        matches!(*self.node.lock().unwrap(), Node::Buffer(_))
     }
 
     pub fn id(&self) -> String {
         self.id.clone()
     }
 
-    pub async fn double_test(&self) -> Result<Vec<u32>, String> {
-        EXECUTOR.get().unwrap().execute_op(&self.id, Operation::DOUBLE).await
+    /// The [Context] this array lives on.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn dimensions(&self) -> [usize; 4] {
+        self.dimensions
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    /// Total number of elements.
+    pub fn len(&self) -> usize {
+        self.dimensions.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the contents of the array back to the host.
+    /// Waiting for the device doesn't block the calling thread, so many reads can be awaited at once.
+    pub async fn to_vec<T: Element>(&self) -> Result<Vec<T>, Error> {
+        self.check_dtype::<T>()?;
+        let storage = self.storage().await?;
+        self.context.executor.read(&storage.id).await
+    }
+
+    /// Reads `len` elements from `offset` on, counting in flat row-major order. Only those elements are copied back.
+    pub async fn read_range<T: Element>(&self, offset: usize, len: usize) -> Result<Vec<T>, Error> {
+        self.check_dtype::<T>()?;
+        let range = utils::check_range(offset, len, self.len())?;
+        let storage = self.storage().await?;
+
+        self.context.executor.read_runs(&storage.id, &[range]).await
+    }
+
+    /// Reads the region `ranges` select along each dimension, in row-major order.
+    ///
+    /// # Example
+    /// ```
+    /// # luma::blocking::block_on(async {
+    /// # let ctx = luma::Context::builder().backend(luma::BackendKind::Cpu).build().await?;
+    /// let matrix = ctx.from_nested([[1u32, 2, 3], [4, 5, 6], [7, 8, 9]]).await?;
+    /// let corner = matrix.read_slice::<u32>(&[1..3, 1..3, 0..1, 0..1]).await?;
+    /// assert_eq!(corner, vec![5, 6, 8, 9]);
+    /// # Ok::<(), luma::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub async fn read_slice<T: Element>(&self, ranges: &[Range<usize>; 4]) -> Result<Vec<T>, Error> {
+        self.check_dtype::<T>()?;
+        utils::check_ranges(&self.dimensions, ranges)?;
+        let storage = self.storage().await?;
+
+        self.context.executor.read_runs(&storage.id, &utils::runs(&self.dimensions, ranges)).await
+    }
+
+    /// Overwrites the elements from `offset` on, counting in flat row-major order, with `data`.
+    /// Pending expressions that read the array keep seeing its old contents.
+    pub async fn write<T: Element>(&mut self, offset: usize, data: &[T]) -> Result<(), Error> {
+        self.check_dtype::<T>()?;
+        let range = utils::check_range(offset, data.len(), self.len())?;
+        let storage = self.storage_mut().await?;
+
+        self.context.executor.write_runs(&storage.id, &[range], data).await
+    }
+
+    /// Overwrites the region `ranges` select along each dimension with `data`, given in row-major order.
+    ///
+    /// # Example
+    /// ```
+    /// # luma::blocking::block_on(async {
+    /// # let ctx = luma::Context::builder().backend(luma::BackendKind::Cpu).build().await?;
+    /// let mut matrix = ctx.zeros::<f32>(&[2, 3, 1, 1]).await?;
+    /// matrix.write_slice(&[0..2, 1..2, 0..1, 0..1], &[1.0f32, 2.0]).await?;
+    /// assert_eq!(matrix.to_vec::<f32>().await?, vec![0.0, 1.0, 0.0, 0.0, 2.0, 0.0]);
+    /// # Ok::<(), luma::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub async fn write_slice<T: Element>(&mut self, ranges: &[Range<usize>; 4], data: &[T]) -> Result<(), Error> {
+        self.check_dtype::<T>()?;
+        let region = utils::check_ranges(&self.dimensions, ranges)?;
+        utils::check_shape(&region, data.len())?;
+        let storage = self.storage_mut().await?;
+
+        self.context.executor.write_runs(&storage.id, &utils::runs(&self.dimensions, ranges), data).await
+    }
+
+    /// Copies the contents into buffers of a new array right away, on the device.
+    /// Unlike [Array::clone], nothing is shared, so the copy takes up memory of its own from the start.
+    pub async fn deep_copy(&self) -> Result<Array, Error> {
+        let storage = self.storage().await?;
+        let copy = self.copy_storage(&storage).await?;
+
+        Ok(self.context.wrap(&self.dimensions, self.dtype, Node::Buffer(copy)))
+    }
+
+    pub async fn double_test(&mut self) -> Result<Vec<u32>, Error> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let storage = self.storage_mut().await?;
+        self.context.executor.execute_op(&storage.id, Operation::Double).await
+    }
+
+    fn check_dtype<T: Element>(&self) -> Result<(), Error> {
+        if T::DTYPE != self.dtype {
+            return Err(Error::DtypeMismatch { expected: T::DTYPE, actual: self.dtype });
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn node(&self) -> Node {
+        self.node.lock().unwrap().clone()
+    }
+
+    /// Returns the buffers holding the contents, running the pending expression first if there is one.
+    pub(crate) async fn storage(&self) -> Result<Arc<Storage>, Error> {
+        let node = self.node();
+        if let Node::Buffer(storage) = node {
+            return Ok(storage);
+        }
+        let storage = self.context.materialize(&node, &self.dimensions, self.dtype).await?;
+
+        // Another task may have evaluated the array in the meantime; its buffers hold the same contents.
+        let mut current = self.node.lock().unwrap();
+        if let Node::Buffer(existing) = &*current {
+            return Ok(existing.clone());
+        }
+        *current = Node::Buffer(storage.clone());
+        Ok(storage)
+    }
+
+    /// Like [Array::storage], but first gives the array buffers of its own if a clone or a pending expression still
+    /// reads the current ones, so they can be written to in place.
+    /// Taking `&mut self` keeps the array from being cloned, or read by a new expression, until the write is done.
+    pub(crate) async fn storage_mut(&mut self) -> Result<Arc<Storage>, Error> {
+        let storage = self.storage().await?;
+        // One reference belongs to the array and one to `storage`; any other to a clone or a pending expression.
+        if Arc::strong_count(&storage) <= 2 {
+            return Ok(storage);
+        }
+        let copy = self.copy_storage(&storage).await?;
+        *self.node.get_mut().unwrap() = Node::Buffer(copy.clone());
+
+        Ok(copy)
+    }
+
+    /// Allocates buffers for an array like this one and copies the contents of `storage` into them on the device.
+    async fn copy_storage(&self, storage: &Storage) -> Result<Arc<Storage>, Error> {
+        let copy = self.context.allocate(&self.dimensions, self.dtype).await?;
+        self.context.executor.copy(&storage.id, &copy.id).await?;
+
+        Ok(copy)
     }
 }
 
+impl Clone for Array {
+    /// Returns an array sharing the contents of this one instead of copying them. The buffers are only copied, on the
+    /// device, once one of the arrays is written to in place. A clone of an array that hasn't been evaluated yet gets
+    /// the same pending expression, which each of them runs on its own; evaluate the array first to run it once.
+    ///
+    /// # Example
+    /// ```
+    /// # luma::blocking::block_on(async {
+    /// # let ctx = luma::Context::builder().backend(luma::BackendKind::Cpu).build().await?;
+    /// let a = ctx.array(&[3, 1, 1, 1], &[1u32, 2, 3]).await?;
+    /// let mut b = a.clone();
+    /// b.write(0, &[10u32]).await?; // `b` gets a copy of its own here.
+    /// assert_eq!(a.to_vec::<u32>().await?, vec![1, 2, 3]);
+    /// assert_eq!(b.to_vec::<u32>().await?, vec![10, 2, 3]);
+    /// # Ok::<(), luma::Error>(())
+    /// # }).unwrap();
+    /// ```
+    fn clone(&self) -> Self {
+        Array {
+            context: self.context.clone(),
+            dimensions: self.dimensions,
+            dtype: self.dtype,
+            id: Uuid::new_v4().into(),
+            node: Mutex::new(self.node()),
+        }
+    }
+}
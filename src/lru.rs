@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Values by name, keeping at most `capacity` of them. Making room for another evicts the least recently used one.
+#[derive(Debug)]
+pub struct Lru<V> {
+    capacity: usize,
+    state: Mutex<LruState<V>>,
+}
+
+#[derive(Debug)]
+struct LruState<V> {
+    entries: HashMap<String, (V, u64)>, // With the tick the entry was last used at.
+    tick: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    /// Creates a cache holding at most `capacity` values. A capacity of zero keeps nothing.
+    pub fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            state: Mutex::new(LruState {
+                entries: HashMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Returns the value under `key`, marking it as the most recently used.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let (value, last_used) = state.entries.get_mut(key)?;
+        *last_used = tick;
+
+        Some(value.clone())
+    }
+
+    /// Adds `value` under `key` unless another caller got there first, returning the value that's kept along with
+    /// the key evicted to make room for it, if any.
+    pub fn insert(&self, key: String, value: V) -> (V, Option<String>) {
+        if self.capacity == 0 {
+            return (value, None);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let mut evicted = None;
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            evicted = state.entries.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(key, _)| key.clone());
+            if let Some(evicted) = &evicted {
+                state.entries.remove(evicted);
+            }
+        }
+
+        (state.entries.entry(key).or_insert((value, tick)).0.clone(), evicted)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
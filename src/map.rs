@@ -0,0 +1,557 @@
+use crate::dtype::DType;
+use crate::error::Error;
+use naga::{BinaryOperator, Expression, Handle, Literal, MathFunction, ScalarKind, Statement, UnaryOperator};
+use crate::lru::Lru;
+use std::sync::Arc;
+
+/// Most arrays [crate::zip_map] combines at once. Together with the output they fill the 8 storage buffers
+/// every device allows per shader stage.
+pub const MAX_MAP_INPUTS: usize = 7;
+
+/// Map kernels a [MapCache] keeps by default, see [crate::ContextBuilder::map_cache_capacity].
+pub const DEFAULT_MAP_CACHE_CAPACITY: usize = 256;
+
+/// Sizes of the caches behind map expressions, as returned by [crate::Context::cache_stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Kernels generated for distinct map expressions, at most [crate::ContextBuilder::map_cache_capacity].
+    pub map_kernels: usize,
+    /// Fused chains of maps whose shaders and pipelines the device keeps, bounded by the same capacity.
+    pub fused_kernels: usize,
+    /// Compiled shader modules, the ones of built-in and custom kernels included.
+    pub shaders: usize,
+    /// Compute pipelines, the ones of built-in and custom kernels included.
+    pub pipelines: usize,
+}
+
+/// The kernels a [crate::Context] generated for recent expressions, by source, so an expression that's used over and
+/// over is only validated and lowered once. Once it holds `capacity` kernels, the least recently used one is evicted.
+#[derive(Debug)]
+pub struct MapCache {
+    kernels: Lru<Arc<MapKernel>>,
+}
+
+impl MapCache {
+    /// Creates a cache holding at most `capacity` kernels. A capacity of zero generates every kernel anew.
+    pub fn new(capacity: usize) -> Self {
+        MapCache {
+            kernels: Lru::new(capacity),
+        }
+    }
+
+    /// Returns the kernel evaluating `expression` once per element, with `arguments` naming the element of each input.
+    /// Syntax and type errors come back as [Error::ShaderCompile], pointing at the column in `expression`.
+    pub fn get(&self, dtype: DType, arguments: &[&str], expression: &str) -> Result<Arc<MapKernel>, Error> {
+        if expression.contains([';', '{', '}']) {
+            return Err(Error::ShaderCompile {
+                shader: expression.to_string(),
+                message: "must be a single expression, without `;`, `{` or `}`".into(),
+            });
+        }
+
+        let (source, expression_line) = generate(dtype, arguments, expression);
+        if let Some(kernel) = self.kernels.get(&source) {
+            return Ok(kernel);
+        }
+
+        let module = validate(&source, expression, expression_line)?;
+        let program = lower(&module);
+        let kernel = Arc::new(MapKernel {
+            expression: expression.to_string(),
+            arguments: arguments.iter().map(|argument| argument.to_string()).collect(),
+            source: source.clone(),
+            dtype,
+            program,
+        });
+
+        Ok(self.kernels.insert(source, kernel).0)
+    }
+
+    /// Number of kernels currently cached.
+    pub fn len(&self) -> usize {
+        self.kernels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kernels.is_empty()
+    }
+}
+
+/// A kernel generated from an element-wise WGSL expression.
+#[derive(Debug)]
+pub struct MapKernel {
+    pub expression: String,     // The expression as the user wrote it.
+    pub arguments: Vec<String>, // Names the expression gives the element of each input.
+    pub source: String,         // Full WGSL source, validated with naga.
+    pub dtype: DType,           // Type of every argument and of the result.
+    program: Result<Expr, String>, // The expression lowered for the CPU backend, or why it can't be.
+}
+
+impl MapKernel {
+    /// The expression as the CPU backend evaluates it.
+    /// Fails with [Error::Unsupported] for WGSL the CPU evaluator doesn't implement, such as vectors.
+    pub(crate) fn program(&self) -> Result<&Expr, Error> {
+        self.program.as_ref().map_err(|message| Error::Unsupported(message.clone()))
+    }
+}
+
+/// Columns the expression is indented by in the generated source.
+const INDENT: &str = "        ";
+
+/// Builds the kernel source, returning it with the 1-based line the expression is on.
+fn generate(dtype: DType, arguments: &[&str], expression: &str) -> (String, u32) {
+    let ty = dtype.wgsl_name();
+    let mut source = header(arguments.len());
+
+    let parameters = arguments.iter().map(|name| format!("{}: {}", name, ty)).collect::<Vec<_>>();
+    source += &format!("fn expression({}) -> {} {{\n    return\n", parameters.join(", "), ty);
+    let expression_line = source.lines().count() as u32 + 1;
+    source += &format!("{}{}\n    ;\n}}\n\n", INDENT, expression);
+    source += &entry_point(dtype, arguments.len());
+
+    (source, expression_line)
+}
+
+/// Declarations every element-wise kernel over `inputs` arrays starts with.
+/// Bindings follow the built-in kernels: the output first, the inputs read-only after it and the length last.
+pub(crate) fn header(inputs: usize) -> String {
+    let mut source = String::from(
+        "override WORKGROUP_SIZE: u32 = 64;\n\
+         \n\
+         struct Params {\n    len: u32,\n    _pad0: u32,\n    _pad1: u32,\n    _pad2: u32,\n}\n\
+         \n\
+         @group(0) @binding(0) var<storage, read_write> output: array<u32>;\n",
+    );
+    for i in 0..inputs {
+        source += &format!("@group(0) @binding({}) var<storage, read> input_{}: array<u32>;\n", i + 1, i);
+    }
+    source += &format!("@group(0) @binding({}) var<uniform> params: Params;\n\n", inputs + 1);
+
+    source
+}
+
+/// The `main` of an element-wise kernel, storing what `expression` returns for the element of every input.
+pub(crate) fn entry_point(dtype: DType, inputs: usize) -> String {
+    let loads = (0..inputs).map(|i| format!("bitcast<{}>(input_{}[i])", dtype.wgsl_name(), i)).collect::<Vec<_>>();
+    format!(
+        "@compute\n\
+         @workgroup_size(WORKGROUP_SIZE)\n\
+         fn main(\n    \
+             @builtin(workgroup_id) workgroup_id: vec3<u32>,\n    \
+             @builtin(num_workgroups) num_workgroups: vec3<u32>,\n    \
+             @builtin(local_invocation_index) local_index: u32,\n\
+         ) {{\n    \
+             let i = (workgroup_id.x + (workgroup_id.y + workgroup_id.z * num_workgroups.y) * num_workgroups.x) * WORKGROUP_SIZE + local_index;\n    \
+             if (i >= params.len) {{\n        return;\n    }}\n    \
+             output[i] = bitcast<u32>(expression({}));\n\
+         }}\n",
+        loads.join(", ")
+    )
+}
+
+/// Parses and validates `source` with naga, so mistakes are reported before wgpu ever sees the kernel.
+fn validate(source: &str, expression: &str, expression_line: u32) -> Result<naga::Module, Error> {
+    let error = |message: String, location: Option<naga::SourceLocation>| {
+        let message = match location {
+            Some(location) if location.line_number == expression_line => format!(
+                "{} at column {}",
+                message,
+                (location.line_position as usize).saturating_sub(INDENT.len()).max(1)
+            ),
+            // The `;` closing the return statement follows on the next line.
+            Some(location) if location.line_number == expression_line + 1 => {
+                format!("{} at the end of the expression", message)
+            }
+            _ => message,
+        };
+        Error::ShaderCompile {
+            shader: expression.to_string(),
+            message,
+        }
+    };
+
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| error(e.message().to_string(), e.location(source)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| {
+            // The outermost error only names the function; the cause is further down the chain.
+            let mut message = e.as_inner().to_string();
+            let mut cause = std::error::Error::source(e.as_inner());
+            while let Some(inner) = cause {
+                message = format!("{}: {}", message, inner);
+                cause = inner.source();
+            }
+            error(message, e.location(source))
+        })?;
+
+    Ok(module)
+}
+
+/// A scalar as the CPU evaluator sees it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Value {
+    F32(f32),
+    U32(u32),
+    I32(i32),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn from_bits(dtype: DType, bits: u32) -> Self {
+        match dtype {
+            DType::F32 => Value::F32(f32::from_bits(bits)),
+            DType::U32 => Value::U32(bits),
+            DType::I32 => Value::I32(bits as i32),
+        }
+    }
+
+    pub fn to_bits(self) -> u32 {
+        match self {
+            Value::F32(v) => v.to_bits(),
+            Value::U32(v) => v,
+            Value::I32(v) => v as u32,
+            Value::Bool(v) => v as u32,
+        }
+    }
+}
+
+/// The body of a map expression, lowered from naga's IR for the CPU backend.
+/// naga has already checked the types, so evaluation never has to.
+#[derive(Debug)]
+pub(crate) enum Expr {
+    Literal(Value),
+    Argument(usize),
+    Unary(UnaryOperator, Box<Expr>),
+    Binary(BinaryOperator, Box<Expr>, Box<Expr>),
+    Math(MathFunction, Vec<Expr>),
+    Select {
+        condition: Box<Expr>,
+        accept: Box<Expr>,
+        reject: Box<Expr>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        kind: ScalarKind,
+        convert: bool, // Value conversion like `f32(x)`; otherwise a `bitcast`.
+    },
+}
+
+/// Lowers the `return` value of the generated `expression` function.
+fn lower(module: &naga::Module) -> Result<Expr, String> {
+    let (_, function) = module
+        .functions
+        .iter()
+        .find(|(_, function)| function.name.as_deref() == Some("expression"))
+        .ok_or("the generated kernel has no expression function")?;
+    let value = function
+        .body
+        .iter()
+        .find_map(|statement| match statement {
+            Statement::Return { value } => *value,
+            _ => None,
+        })
+        .ok_or("the expression function doesn't return a value")?;
+
+    lower_expression(module, &function.expressions, value)
+}
+
+fn lower_expression(module: &naga::Module, arena: &naga::Arena<Expression>, handle: Handle<Expression>) -> Result<Expr, String> {
+    let lower = |handle| lower_expression(module, arena, handle).map(Box::new);
+
+    Ok(match &arena[handle] {
+        Expression::Literal(literal) => Expr::Literal(match *literal {
+            Literal::F32(v) => Value::F32(v),
+            Literal::U32(v) => Value::U32(v),
+            Literal::I32(v) => Value::I32(v),
+            Literal::Bool(v) => Value::Bool(v),
+            other => return Err(format!("the CPU backend doesn't support {:?} literals", other)),
+        }),
+        Expression::Constant(constant) => {
+            lower_expression(module, &module.global_expressions, module.constants[*constant].init)?
+        }
+        Expression::FunctionArgument(index) => Expr::Argument(*index as usize),
+        Expression::Unary { op, expr } => Expr::Unary(*op, lower(*expr)?),
+        Expression::Binary { op, left, right } => Expr::Binary(*op, lower(*left)?, lower(*right)?),
+        Expression::Select { condition, accept, reject } => Expr::Select {
+            condition: lower(*condition)?,
+            accept: lower(*accept)?,
+            reject: lower(*reject)?,
+        },
+        Expression::As { expr, kind, convert } => Expr::Cast {
+            expr: lower(*expr)?,
+            kind: *kind,
+            convert: convert.is_some(),
+        },
+        Expression::Math { fun, arg, arg1, arg2, arg3 } => {
+            if !SUPPORTED_MATH.contains(fun) {
+                return Err(format!("the CPU backend doesn't support {:?}", fun));
+            }
+            let arguments = [Some(*arg), *arg1, *arg2, *arg3]
+                .into_iter()
+                .flatten()
+                .map(|handle| lower_expression(module, arena, handle))
+                .collect::<Result<_, _>>()?;
+            Expr::Math(*fun, arguments)
+        }
+        other => return Err(format!("the CPU backend doesn't support {:?} in map expressions", other)),
+    })
+}
+
+/// Built-in functions the CPU evaluator implements.
+const SUPPORTED_MATH: &[MathFunction] = &[
+    MathFunction::Abs,
+    MathFunction::Min,
+    MathFunction::Max,
+    MathFunction::Clamp,
+    MathFunction::Saturate,
+    MathFunction::Sign,
+    MathFunction::Sin,
+    MathFunction::Cos,
+    MathFunction::Tan,
+    MathFunction::Asin,
+    MathFunction::Acos,
+    MathFunction::Atan,
+    MathFunction::Atan2,
+    MathFunction::Sinh,
+    MathFunction::Cosh,
+    MathFunction::Tanh,
+    MathFunction::Asinh,
+    MathFunction::Acosh,
+    MathFunction::Atanh,
+    MathFunction::Radians,
+    MathFunction::Degrees,
+    MathFunction::Ceil,
+    MathFunction::Floor,
+    MathFunction::Round,
+    MathFunction::Fract,
+    MathFunction::Trunc,
+    MathFunction::Exp,
+    MathFunction::Exp2,
+    MathFunction::Log,
+    MathFunction::Log2,
+    MathFunction::Pow,
+    MathFunction::Sqrt,
+    MathFunction::InverseSqrt,
+    MathFunction::Fma,
+    MathFunction::Mix,
+    MathFunction::Step,
+    MathFunction::SmoothStep,
+];
+
+impl Expr {
+    /// Evaluates the expression for one element, following WGSL: integers wrap, integer division by zero yields
+    /// the left operand and the remainder by zero is zero.
+    pub fn eval(&self, arguments: &[Value]) -> Value {
+        match self {
+            Expr::Literal(value) => *value,
+            Expr::Argument(index) => arguments[*index],
+            Expr::Unary(op, expr) => match (op, expr.eval(arguments)) {
+                (UnaryOperator::Negate, Value::F32(v)) => Value::F32(-v),
+                (UnaryOperator::Negate, Value::I32(v)) => Value::I32(v.wrapping_neg()),
+                (UnaryOperator::LogicalNot, Value::Bool(v)) => Value::Bool(!v),
+                (UnaryOperator::BitwiseNot, Value::U32(v)) => Value::U32(!v),
+                (UnaryOperator::BitwiseNot, Value::I32(v)) => Value::I32(!v),
+                (op, value) => unreachable!("naga accepted {:?} on {:?}", op, value),
+            },
+            Expr::Binary(op, left, right) => binary(*op, left.eval(arguments), right.eval(arguments)),
+            Expr::Select { condition, accept, reject } => match condition.eval(arguments) {
+                Value::Bool(true) => accept.eval(arguments),
+                _ => reject.eval(arguments),
+            },
+            Expr::Cast { expr, kind, convert } => cast(expr.eval(arguments), *kind, *convert),
+            Expr::Math(fun, args) => {
+                let args = args.iter().map(|arg| arg.eval(arguments)).collect::<Vec<_>>();
+                math(*fun, &args)
+            }
+        }
+    }
+}
+
+fn binary(op: BinaryOperator, left: Value, right: Value) -> Value {
+    use BinaryOperator as B;
+
+    match (left, right) {
+        (Value::F32(a), Value::F32(b)) => match op {
+            B::Add => Value::F32(a + b),
+            B::Subtract => Value::F32(a - b),
+            B::Multiply => Value::F32(a * b),
+            B::Divide => Value::F32(a / b),
+            B::Modulo => Value::F32(a % b),
+            _ => compare(op, a, b),
+        },
+        (Value::U32(a), Value::U32(b)) => match op {
+            B::Add => Value::U32(a.wrapping_add(b)),
+            B::Subtract => Value::U32(a.wrapping_sub(b)),
+            B::Multiply => Value::U32(a.wrapping_mul(b)),
+            B::Divide => Value::U32(a.checked_div(b).unwrap_or(a)),
+            B::Modulo => Value::U32(a.checked_rem(b).unwrap_or(0)),
+            B::And => Value::U32(a & b),
+            B::InclusiveOr => Value::U32(a | b),
+            B::ExclusiveOr => Value::U32(a ^ b),
+            B::ShiftLeft => Value::U32(a.wrapping_shl(b)),
+            B::ShiftRight => Value::U32(a.wrapping_shr(b)),
+            _ => compare(op, a, b),
+        },
+        (Value::I32(a), Value::I32(b)) => match op {
+            B::Add => Value::I32(a.wrapping_add(b)),
+            B::Subtract => Value::I32(a.wrapping_sub(b)),
+            B::Multiply => Value::I32(a.wrapping_mul(b)),
+            B::Divide => Value::I32(a.checked_div(b).unwrap_or(a)),
+            B::Modulo => Value::I32(a.checked_rem(b).unwrap_or(0)),
+            B::And => Value::I32(a & b),
+            B::InclusiveOr => Value::I32(a | b),
+            B::ExclusiveOr => Value::I32(a ^ b),
+            _ => compare(op, a, b),
+        },
+        (Value::I32(a), Value::U32(b)) => match op {
+            B::ShiftLeft => Value::I32(a.wrapping_shl(b)),
+            B::ShiftRight => Value::I32(a.wrapping_shr(b)),
+            _ => unreachable!("naga accepted {:?} on {:?} and {:?}", op, left, right),
+        },
+        (Value::Bool(a), Value::Bool(b)) => match op {
+            B::LogicalAnd | B::And => Value::Bool(a && b),
+            B::LogicalOr | B::InclusiveOr => Value::Bool(a || b),
+            _ => compare(op, a, b),
+        },
+        _ => unreachable!("naga accepted {:?} on {:?} and {:?}", op, left, right),
+    }
+}
+
+fn compare<T: PartialOrd>(op: BinaryOperator, a: T, b: T) -> Value {
+    Value::Bool(match op {
+        BinaryOperator::Equal => a == b,
+        BinaryOperator::NotEqual => a != b,
+        BinaryOperator::Less => a < b,
+        BinaryOperator::LessEqual => a <= b,
+        BinaryOperator::Greater => a > b,
+        BinaryOperator::GreaterEqual => a >= b,
+        _ => unreachable!("naga accepted {:?} here", op),
+    })
+}
+
+fn cast(value: Value, kind: ScalarKind, convert: bool) -> Value {
+    if !convert {
+        let bits = value.to_bits();
+        return match kind {
+            ScalarKind::Float => Value::F32(f32::from_bits(bits)),
+            ScalarKind::Sint => Value::I32(bits as i32),
+            _ => Value::U32(bits),
+        };
+    }
+
+    match (kind, value) {
+        (ScalarKind::Float, Value::F32(v)) => Value::F32(v),
+        (ScalarKind::Float, Value::U32(v)) => Value::F32(v as f32),
+        (ScalarKind::Float, Value::I32(v)) => Value::F32(v as f32),
+        (ScalarKind::Float, Value::Bool(v)) => Value::F32(v as u32 as f32),
+        (ScalarKind::Uint, Value::F32(v)) => Value::U32(v as u32),
+        (ScalarKind::Sint, Value::F32(v)) => Value::I32(v as i32),
+        (ScalarKind::Bool, Value::F32(v)) => Value::Bool(v != 0.0),
+        (ScalarKind::Bool, value) => Value::Bool(value.to_bits() != 0),
+        (ScalarKind::Sint, value) => Value::I32(value.to_bits() as i32),
+        (_, value) => Value::U32(value.to_bits()),
+    }
+}
+
+fn math(fun: MathFunction, args: &[Value]) -> Value {
+    use MathFunction as M;
+
+    match args {
+        [Value::F32(a)] => Value::F32(match fun {
+            M::Abs => a.abs(),
+            M::Saturate => a.clamp(0.0, 1.0),
+            M::Sign => if *a > 0.0 { 1.0 } else if *a < 0.0 { -1.0 } else { 0.0 },
+            M::Sin => a.sin(),
+            M::Cos => a.cos(),
+            M::Tan => a.tan(),
+            M::Asin => a.asin(),
+            M::Acos => a.acos(),
+            M::Atan => a.atan(),
+            M::Sinh => a.sinh(),
+            M::Cosh => a.cosh(),
+            M::Tanh => a.tanh(),
+            M::Asinh => a.asinh(),
+            M::Acosh => a.acosh(),
+            M::Atanh => a.atanh(),
+            M::Radians => a.to_radians(),
+            M::Degrees => a.to_degrees(),
+            M::Ceil => a.ceil(),
+            M::Floor => a.floor(),
+            M::Round => a.round_ties_even(),
+            M::Fract => a - a.floor(),
+            M::Trunc => a.trunc(),
+            M::Exp => a.exp(),
+            M::Exp2 => a.exp2(),
+            M::Log => a.ln(),
+            M::Log2 => a.log2(),
+            M::Sqrt => a.sqrt(),
+            M::InverseSqrt => 1.0 / a.sqrt(),
+            _ => unreachable!("naga accepted {:?} with one argument", fun),
+        }),
+        [Value::F32(a), Value::F32(b)] => Value::F32(match fun {
+            M::Min => a.min(*b),
+            M::Max => a.max(*b),
+            M::Atan2 => a.atan2(*b),
+            M::Pow => a.powf(*b),
+            M::Step => if a <= b { 1.0 } else { 0.0 },
+            _ => unreachable!("naga accepted {:?} with two arguments", fun),
+        }),
+        [Value::F32(a), Value::F32(b), Value::F32(c)] => Value::F32(match fun {
+            M::Clamp => a.max(*b).min(*c),
+            M::Fma => a.mul_add(*b, *c),
+            M::Mix => a * (1.0 - c) + b * c,
+            M::SmoothStep => {
+                let t = ((c - a) / (b - a)).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+            _ => unreachable!("naga accepted {:?} with three arguments", fun),
+        }),
+        [Value::U32(a)] => Value::U32(match fun {
+            M::Abs => *a,
+            M::Sign => (*a > 0) as u32,
+            _ => unreachable!("naga accepted {:?} on u32", fun),
+        }),
+        [Value::I32(a)] => Value::I32(match fun {
+            M::Abs => a.wrapping_abs(),
+            M::Sign => a.signum(),
+            _ => unreachable!("naga accepted {:?} on i32", fun),
+        }),
+        [Value::U32(a), Value::U32(b)] => Value::U32(integer_pair(fun, *a, *b)),
+        [Value::I32(a), Value::I32(b)] => Value::I32(integer_pair(fun, *a, *b)),
+        [Value::U32(a), Value::U32(b), Value::U32(c)] if fun == M::Clamp => Value::U32((*a).max(*b).min(*c)),
+        [Value::I32(a), Value::I32(b), Value::I32(c)] if fun == M::Clamp => Value::I32((*a).max(*b).min(*c)),
+        _ => unreachable!("naga accepted {:?} on {:?}", fun, args),
+    }
+}
+
+fn integer_pair<T: Ord>(fun: MathFunction, a: T, b: T) -> T {
+    match fun {
+        MathFunction::Min => a.min(b),
+        MathFunction::Max => a.max(b),
+        _ => unreachable!("naga accepted {:?} on integers", fun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_cache_evicts_the_least_recently_used_kernel() {
+        let cache = MapCache::new(2);
+        let a = cache.get(DType::F32, &["x"], "x + 1.0").unwrap();
+        let b = cache.get(DType::F32, &["x"], "x + 2.0").unwrap();
+        assert!(Arc::ptr_eq(&a, &cache.get(DType::F32, &["x"], "x + 1.0").unwrap()));
+
+        cache.get(DType::F32, &["x"], "x + 3.0").unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(Arc::ptr_eq(&a, &cache.get(DType::F32, &["x"], "x + 1.0").unwrap()));
+        assert!(!Arc::ptr_eq(&b, &cache.get(DType::F32, &["x"], "x + 2.0").unwrap()));
+
+        for i in 0..100 {
+            cache.get(DType::U32, &["x"], &format!("x * {}u", i)).unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::error::Error;
+use std::sync::{Arc, Mutex};
+
+/// Bytes a backend holds, by what they're used for, as returned by [crate::Context::memory_stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    /// Storage buffers of live arrays, including the ones of dropped arrays that pending commands still use.
+    pub storage: u64,
+    /// Buffers reads copy through on their way back to the host.
+    pub staging: u64,
+    /// Storage buffers of freed arrays kept for reuse.
+    pub pool: u64,
+    /// Arrays whose buffers are allocated. Expressions that haven't been evaluated yet hold none.
+    pub arrays: usize,
+    /// The highest [MemoryStats::total] so far.
+    pub peak: u64,
+}
+
+impl MemoryStats {
+    /// Bytes held in every category together, which is what [crate::ContextBuilder::memory_limit] caps.
+    pub fn total(&self) -> u64 {
+        self.storage + self.staging + self.pool
+    }
+
+    fn bytes_mut(&mut self, category: Category) -> &mut u64 {
+        match category {
+            Category::Storage => &mut self.storage,
+            Category::Staging => &mut self.staging,
+            Category::Pool => &mut self.pool,
+        }
+    }
+}
+
+/// What an [Allocation] is counted as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Storage,
+    Staging,
+    Pool,
+}
+
+/// Counts the bytes a backend allocates and enforces its memory limit.
+#[derive(Debug)]
+pub struct MemoryTracker {
+    limit: Option<u64>,
+    usage: Mutex<MemoryStats>,
+}
+
+impl MemoryTracker {
+    pub fn new(limit: Option<u64>) -> Arc<Self> {
+        Arc::new(MemoryTracker {
+            limit,
+            usage: Mutex::new(MemoryStats::default()),
+        })
+    }
+
+    /// Accounts for `bytes` about to be allocated, which count until the returned [Allocation] is dropped.
+    /// Fails with [Error::OutOfMemory] if they would take the total over the limit.
+    pub fn reserve(self: &Arc<Self>, category: Category, bytes: u64) -> Result<Allocation, Error> {
+        let mut usage = self.usage.lock().unwrap();
+        let total = usage.total() + bytes;
+        if let Some(limit) = self.limit.filter(|&limit| total > limit) {
+            return Err(Error::OutOfMemory(format!(
+                "allocating {} bytes would take the {} bytes in use over the memory limit of {} bytes",
+                bytes,
+                usage.total(),
+                limit
+            )));
+        }
+        *usage.bytes_mut(category) += bytes;
+        usage.peak = usage.peak.max(total);
+
+        Ok(Allocation {
+            memory: self.clone(),
+            category,
+            bytes,
+        })
+    }
+
+    /// The current usage. [MemoryStats::arrays] is left for the backend to fill in.
+    pub fn stats(&self) -> MemoryStats {
+        *self.usage.lock().unwrap()
+    }
+}
+
+/// Bytes reserved with [MemoryTracker::reserve]. Kept next to the buffer they're for and released along with it.
+#[derive(Debug)]
+pub struct Allocation {
+    memory: Arc<MemoryTracker>,
+    category: Category,
+    bytes: u64,
+}
+
+impl Allocation {
+    /// Counts the bytes as `category` from now on, e.g. when a buffer moves into the pool.
+    pub fn move_to(&mut self, category: Category) {
+        let mut usage = self.memory.usage.lock().unwrap();
+        *usage.bytes_mut(self.category) -= self.bytes;
+        *usage.bytes_mut(category) += self.bytes;
+        self.category = category;
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        *self.memory.usage.lock().unwrap().bytes_mut(self.category) -= self.bytes;
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::Error;
+use crate::gpu::GpuHandle;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use wgpu::{BindGroupLayout, BufferBindingType, ComputePipeline, Features, ShaderModule};
+
+/// What a compiled pipeline depends on.
+/// The dtype is a uniform parameter, so it doesn't need its own pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub kernel: String,
+    pub workgroup_size: Option<u32>, // Value of the shader's `WORKGROUP_SIZE` override, if it has one.
+    pub layout: Vec<BufferBindingType>, // Type of every binding in group 0, in `@binding` order.
+}
+
+/// A compute pipeline together with the layout its bind groups have to be created with.
+#[derive(Debug)]
+pub struct CachedPipeline {
+    pub bind_group_layout: BindGroupLayout,
+    pub pipeline: ComputePipeline,
+}
+
+/// wgpu's driver level pipeline cache and the file it's persisted to.
+#[derive(Debug)]
+struct DiskCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+}
+
+/// Compute pipelines created so far, so every kernel is only compiled into a pipeline once per device.
+#[derive(Debug)]
+pub struct Pipelines {
+    pipelines: RwLock<HashMap<PipelineKey, Arc<CachedPipeline>>>,
+    disk: Option<DiskCache>,
+}
+
+impl Pipelines {
+    /// Creates an empty cache. With a `directory`, and a device that supports [Features::PIPELINE_CACHE],
+    /// the driver's compiled pipelines are loaded from and saved to a file in it so warm starts skip shader compilation.
+    pub fn new(adapter: &GpuHandle, directory: Option<&Path>) -> Self {
+        let disk = directory.and_then(|directory| {
+            if !adapter.device.features().contains(Features::PIPELINE_CACHE) {
+                debug!("The device doesn't support pipeline caches; not persisting pipelines");
+                return None;
+            }
+            let file_name = wgpu::util::pipeline_cache_key(&adapter.info)?;
+            let path = directory.join(file_name);
+            let data = std::fs::read(&path).ok();
+            debug!("Loaded {} bytes of pipeline cache from {:?}", data.as_ref().map_or(0, Vec::len), path);
+            // SAFETY: The data was written by `PipelineCache::get_data` on an earlier run. The file name is derived
+            // from the adapter and driver, and wgpu falls back to an empty cache if the data doesn't fit the device.
+            let cache = unsafe {
+                adapter.device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("Pipeline Cache"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+            };
+
+            Some(DiskCache { cache, path })
+        });
+
+        Pipelines {
+            pipelines: RwLock::new(HashMap::new()),
+            disk,
+        }
+    }
+
+    /// Returns the pipeline for `key`, compiling it from `module` the first time it's asked for.
+    /// Pipelines that fail to compile are not cached.
+    pub async fn get_or_create(&self, adapter: &GpuHandle, key: &PipelineKey, module: &ShaderModule) -> Result<Arc<CachedPipeline>, Error> {
+        if let Some(pipeline) = self.pipelines.read().unwrap().get(key) {
+            return Ok(pipeline.clone());
+        }
+
+        let constants = key
+            .workgroup_size
+            .map(|size| ("WORKGROUP_SIZE".to_string(), size as f64))
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let pipeline = adapter.scoped(|device| {
+            // `binding` here refers to the `binding` of a buffer in the shader (`layout(set = 0, binding = 0) buffer`).
+            let layout_entries = key
+                .layout
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| wgpu::BindGroupLayoutEntry {
+                    binding: i as u32,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: *ty,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                })
+                .collect::<Vec<_>>();
+            let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout"),
+                entries: &layout_entries,
+            });
+
+            // We need to define the layout of our pipeline (shader in this case) we're using as well.
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            // A pipeline specifies the operation of a shader
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline"),
+                layout: Some(&pipeline_layout),
+                module,
+                entry_point: Some("main"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                cache: self.disk.as_ref().map(|disk| &disk.cache),
+            });
+
+            CachedPipeline { bind_group_layout, pipeline }
+        }).await?;
+
+        // Another thread may have compiled the same pipeline meanwhile; keep whichever got in first.
+        let pipeline = self.pipelines.write().unwrap().entry(key.clone()).or_insert(Arc::new(pipeline)).clone();
+        self.persist();
+
+        Ok(pipeline)
+    }
+
+    /// Drops every pipeline created for the kernel `name`.
+    pub fn remove(&self, name: &str) {
+        self.pipelines.write().unwrap().retain(|key, _| key.kernel != name);
+    }
+
+    /// Number of pipelines created so far and still cached.
+    pub fn len(&self) -> usize {
+        self.pipelines.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the driver's pipeline cache to disk, if persistence is on.
+    /// The data goes to a temporary file first, which then replaces the old cache atomically.
+    fn persist(&self) {
+        let Some(disk) = &self.disk else {
+            return;
+        };
+        let Some(data) = disk.cache.get_data() else {
+            return;
+        };
+
+        let temporary = disk.path.with_extension("tmp");
+        let written = disk
+            .path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&temporary, &data))
+            .and_then(|_| std::fs::rename(&temporary, &disk.path));
+        if let Err(e) = written {
+            warn!("Could not save the pipeline cache to {:?}: {}", disk.path, e);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::error::Error;
+use std::thread::JoinHandle;
+use wgpu::{Device, Maintain};
+
+/// Drives `device.poll` on a thread of its own, so the callbacks of `map_async` and `on_submitted_work_done` fire
+/// while the tasks waiting on them, and the runtime they run on, stay free.
+///
+/// The thread sleeps until it's woken. Every wake-up polls until everything submitted so far has finished, which
+/// covers any wake-ups that arrived in the meantime, so many waiting tasks share a single poll.
+#[derive(Debug)]
+pub struct Poller {
+    wake: Option<flume::Sender<()>>, // Dropped first, which tells the thread to stop.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Poller {
+    pub fn new(device: Device) -> Result<Self, Error> {
+        let (wake, requests) = flume::unbounded::<()>();
+        let thread = std::thread::Builder::new()
+            .name("luma-poller".into())
+            .spawn(move || {
+                while requests.recv().is_ok() {
+                    while requests.try_recv().is_ok() {}
+                    device.poll(Maintain::Wait);
+                }
+            })
+            .map_err(|e| Error::DeviceRequest(format!("could not start the device poller: {}", e)))?;
+
+        Ok(Poller {
+            wake: Some(wake),
+            thread: Some(thread),
+        })
+    }
+
+    /// Makes sure the callbacks of everything submitted before this call fire. Register them first.
+    pub fn wake(&self) {
+        if let Some(wake) = &self.wake {
+            let _ = wake.send(());
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.wake.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Bytes of idle buffers a [BufferPool] keeps around by default, see [crate::ContextBuilder::pool_capacity].
+pub const DEFAULT_POOL_CAPACITY: u64 = 256 << 20;
+
+/// Counters of a [BufferPool], as returned by [crate::Context::pool_stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Allocations served with a recycled buffer.
+    pub hits: u64,
+    /// Allocations that had to create a new buffer.
+    pub misses: u64,
+    /// Idle buffers currently held by the pool.
+    pub buffers: usize,
+    /// Bytes of the idle buffers currently held by the pool.
+    pub bytes: u64,
+}
+
+/// The size of the bucket `size` bytes fall into, which is the size buffers for them are created with.
+/// Every power of two is split into four buckets, so no more than a quarter of a buffer goes unused.
+/// Buckets are multiples of four bytes and never empty, since wgpu can't bind zero sized buffers:
+/// 0 bytes fall into the bucket of 4, 1000 into 1024 and 1025 into 1536.
+pub fn bucket(size: u64) -> u64 {
+    let size = size.max(4);
+    let step = (size.next_power_of_two() / 4).max(4);
+
+    size.next_multiple_of(step)
+}
+
+/// Recycles the buffers of freed arrays for new arrays of about the same size.
+/// Buffers are grouped by [bucket], so an allocation reuses any idle buffer of its bucket.
+#[derive(Debug)]
+pub struct BufferPool<T> {
+    capacity: u64,
+    state: Mutex<PoolState<T>>,
+}
+
+#[derive(Debug)]
+struct PoolState<T> {
+    idle: HashMap<u64, Vec<T>>, // By bucket size.
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<T> BufferPool<T> {
+    /// Creates a pool holding at most `capacity` bytes of idle buffers. A capacity of zero disables pooling.
+    pub fn new(capacity: u64) -> Self {
+        BufferPool {
+            capacity,
+            state: Mutex::new(PoolState {
+                idle: HashMap::new(),
+                bytes: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Takes an idle buffer of the `bucket` size, counting a hit if there is one and a miss otherwise.
+    pub fn take(&self, bucket: u64) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        match state.idle.get_mut(&bucket).and_then(Vec::pop) {
+            Some(buffer) => {
+                state.hits += 1;
+                state.bytes -= bucket;
+                Some(buffer)
+            }
+            None => {
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Hands back a buffer of the `bucket` size for reuse. It's dropped instead if keeping it would exceed the capacity.
+    pub fn give(&self, bucket: u64, buffer: T) {
+        let mut state = self.state.lock().unwrap();
+        if state.bytes + bucket > self.capacity {
+            return;
+        }
+        state.bytes += bucket;
+        state.idle.entry(bucket).or_default().push(buffer);
+    }
+
+    /// Drops every idle buffer. The hit and miss counters are kept.
+    pub fn clear(&self) {
+        let idle = {
+            let mut state = self.state.lock().unwrap();
+            state.bytes = 0;
+            std::mem::take(&mut state.idle)
+        };
+        // Freed outside of the lock, since dropping device buffers can take a while.
+        drop(idle);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state.lock().unwrap();
+
+        PoolStats {
+            hits: state.hits,
+            misses: state.misses,
+            buffers: state.idle.values().map(Vec::len).sum(),
+            bytes: state.bytes,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::error::Error;
+use crate::gpu::GpuHandle;
+use log::debug;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use wgpu::ShaderModule;
+
+/// Every built-in kernel, embedded into the binary at compile time and named after its [crate::execution::Operation].
+pub static BUILTIN_SHADERS: &[(&str, &str)] = &[
+    ("double", include_str!("../operations/double.wgsl")),
+    ("fill", include_str!("../operations/fill.wgsl")),
+    ("ramp", include_str!("../operations/ramp.wgsl")),
+    ("eye", include_str!("../operations/eye.wgsl")),
+    ("tile", include_str!("../operations/tile.wgsl")),
+];
+
+/// Reads every `.wgsl` file in `directory`, returning `(name, source)` pairs where the name is the file stem.
+/// Anything else in the directory, including subdirectories, is skipped.
+pub fn read_directory(directory: &Path) -> Result<Vec<(String, String)>, Error> {
+    let entries = std::fs::read_dir(directory).map_err(|e| Error::ShaderCompile {
+        shader: directory.display().to_string(),
+        message: format!("Could not read shader directory: {}", e),
+    })?;
+
+    let mut shaders = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| Error::ShaderCompile {
+                shader: directory.display().to_string(),
+                message: format!("Could not read shader directory: {}", e),
+            })?
+            .path();
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) if path.is_file() && path.extension().is_some_and(|extension| extension == "wgsl") => name,
+            _ => {
+                debug!("Skipping {:?}, which isn't a .wgsl file", path);
+                continue;
+            }
+        };
+
+        let source = std::fs::read_to_string(&path).map_err(|e| Error::ShaderCompile {
+            shader: path.display().to_string(),
+            message: format!("Could not read file contents: {}", e),
+        })?;
+        shaders.push((name.to_string(), source));
+    }
+
+    Ok(shaders)
+}
+
+/// WGSL sources by kernel name, compiled into [ShaderModule]s the first time each one is used.
+/// Shared between threads; a kernel is only ever compiled once per device.
+#[derive(Debug)]
+pub struct ShaderCache {
+    sources: RwLock<HashMap<String, Cow<'static, str>>>,
+    modules: RwLock<HashMap<String, Arc<ShaderModule>>>,
+}
+
+impl ShaderCache {
+    /// Registers the built-in shaders, followed by every `.wgsl` file in `directory` if one is given.
+    /// A user shader named like a built-in one replaces it. Nothing is compiled yet.
+    pub fn new(directory: Option<&Path>) -> Result<Self, Error> {
+        let mut sources = BUILTIN_SHADERS
+            .iter()
+            .map(|(name, source)| (name.to_string(), Cow::Borrowed(*source)))
+            .collect::<HashMap<_, _>>();
+
+        if let Some(directory) = directory {
+            for (name, source) in read_directory(directory)? {
+                if sources.insert(name.clone(), Cow::Owned(source)).is_some() {
+                    debug!("{:?} from {:?} replaces the built-in shader", name, directory);
+                }
+            }
+        }
+
+        Ok(ShaderCache {
+            sources: RwLock::new(sources),
+            modules: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the compiled module for the kernel `name`, compiling it first if this is the first time it's used.
+    pub async fn module(&self, adapter: &GpuHandle, name: &str) -> Result<Arc<ShaderModule>, Error> {
+        if let Some(module) = self.modules.read().unwrap().get(name) {
+            return Ok(module.clone());
+        }
+        let Some(source) = self.sources.read().unwrap().get(name).cloned() else {
+            return Err(Error::ShaderCompile {
+                shader: name.to_string(),
+                message: "no shader is registered under this name".into(),
+            });
+        };
+
+        debug!("Compiling shader {:?}", name);
+        let module = compile_shader(adapter, name, source).await?;
+        // Another thread may have compiled the same shader meanwhile; keep whichever got in first.
+        let module = self.modules.write().unwrap().entry(name.to_string()).or_insert(Arc::new(module)).clone();
+
+        Ok(module)
+    }
+
+    /// Adds a kernel under `name`, compiling it right away so mistakes in the source are reported here.
+    /// Names can't be reused, since pipelines compiled from the old source would otherwise live on.
+    pub async fn register(&self, adapter: &GpuHandle, name: &str, source: String) -> Result<(), Error> {
+        if self.sources.read().unwrap().contains_key(name) {
+            return Err(Error::InvalidArgument(format!("a kernel named {:?} is already registered", name)));
+        }
+        let module = compile_shader(adapter, name, Cow::Borrowed(&source)).await?;
+
+        let mut sources = self.sources.write().unwrap();
+        if sources.contains_key(name) {
+            return Err(Error::InvalidArgument(format!("a kernel named {:?} is already registered", name)));
+        }
+        sources.insert(name.to_string(), Cow::Owned(source));
+        self.modules.write().unwrap().insert(name.to_string(), Arc::new(module));
+
+        Ok(())
+    }
+
+    /// Whether a shader is known under `name`, compiled or not.
+    pub fn contains(&self, name: &str) -> bool {
+        self.sources.read().unwrap().contains_key(name)
+    }
+
+    /// Adds a generated kernel's source unless something is registered under `name` already.
+    /// Like the built-in kernels it is compiled on first use.
+    pub fn insert(&self, name: &str, source: &str) {
+        self.sources
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| Cow::Owned(source.to_string()));
+    }
+
+    /// Forgets the kernel `name` along with its compiled module, e.g. once a generated kernel goes out of use.
+    pub fn remove(&self, name: &str) {
+        self.sources.write().unwrap().remove(name);
+        self.modules.write().unwrap().remove(name);
+    }
+
+    /// Number of compiled modules.
+    pub fn modules(&self) -> usize {
+        self.modules.read().unwrap().len()
+    }
+
+    /// Compiles the kernels in `names` ahead of their first use, so that use doesn't pay for the compilation.
+    pub async fn prewarm(&self, adapter: &GpuHandle, names: &[String]) -> Result<(), Error> {
+        for name in names {
+            self.module(adapter, name).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compiles a single WGSL source, reporting parse and validation failures as [Error::ShaderCompile].
+async fn compile_shader(adapter: &GpuHandle, name: &str, source: Cow<'_, str>) -> Result<ShaderModule, Error> {
+    adapter
+        .scoped(|device| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(source),
+            })
+        })
+        .await
+        .map_err(|e| Error::ShaderCompile {
+            shader: name.to_string(),
+            message: e.to_string(),
+        })
+}
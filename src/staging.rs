@@ -0,0 +1,71 @@
+use crate::memory::Allocation;
+use std::sync::Mutex;
+use wgpu::Buffer;
+
+/// Default for [crate::ContextBuilder::staging_budget].
+pub const DEFAULT_STAGING_BUDGET: u64 = 64 << 20;
+
+/// Idle staging buffers kept by a [StagingRing]; once there are more, the smallest one is freed.
+const RING_SIZE: usize = 4;
+
+/// A mappable buffer, with the bytes it's accounted for.
+#[derive(Debug)]
+pub struct StagingBuffer {
+    pub buffer: Buffer,
+    pub allocation: Allocation,
+}
+
+/// The mappable buffers reads copy through, shared by every array of a device.
+/// They're only created when something is read back, and reused by later reads instead of being freed.
+#[derive(Debug)]
+pub struct StagingRing {
+    budget: u64, // Largest staging buffer; reads of more bytes are split into chunks of this size.
+    idle: Mutex<Vec<StagingBuffer>>,
+}
+
+impl StagingRing {
+    /// `budget` is rounded down to a multiple of `wgpu::COPY_BUFFER_ALIGNMENT`, the granularity of copies.
+    pub fn new(budget: u64) -> Self {
+        StagingRing {
+            budget: (budget - budget % wgpu::COPY_BUFFER_ALIGNMENT).max(wgpu::COPY_BUFFER_ALIGNMENT),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// The size to create a staging buffer with for a chunk of `len` bytes, leaving room for larger chunks later.
+    pub fn size_for(&self, len: u64) -> u64 {
+        len.next_power_of_two().min(self.budget)
+    }
+
+    /// Takes the smallest idle buffer that holds at least `len` bytes.
+    pub fn take(&self, len: u64) -> Option<StagingBuffer> {
+        let mut idle = self.idle.lock().unwrap();
+        let index = idle
+            .iter()
+            .enumerate()
+            .filter(|(_, staging)| staging.buffer.size() >= len)
+            .min_by_key(|(_, staging)| staging.buffer.size())
+            .map(|(index, _)| index)?;
+
+        Some(idle.swap_remove(index))
+    }
+
+    /// Hands back an unmapped buffer for later reads.
+    pub fn give(&self, staging: StagingBuffer) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.push(staging);
+        if idle.len() > RING_SIZE {
+            let smallest = (0..idle.len()).min_by_key(|&index| idle[index].buffer.size()).unwrap();
+            idle.swap_remove(smallest);
+        }
+    }
+
+    /// Frees every idle buffer.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,39 @@
+/// Lets async callers take turns at something, one at a time, without blocking the threads they run on.
+/// It's a channel holding a single token: taking a turn receives it and dropping the [Turn] sends it back.
+#[derive(Debug)]
+pub struct Turns {
+    token: (flume::Sender<()>, flume::Receiver<()>),
+}
+
+/// A caller's turn, handed on to the next waiting caller when dropped.
+pub struct Turn<'a>(&'a flume::Sender<()>);
+
+impl Turns {
+    pub fn new() -> Self {
+        let token = flume::bounded(1);
+        let _ = token.0.send(());
+
+        Turns { token }
+    }
+
+    /// Waits until every earlier turn has ended.
+    pub async fn take(&self) -> Turn<'_> {
+        let (sender, receiver) = &self.token;
+        // The sender lives right next to the receiver, so the channel can't disconnect.
+        let _ = receiver.recv_async().await;
+
+        Turn(sender)
+    }
+}
+
+impl Default for Turns {
+    fn default() -> Self {
+        Turns::new()
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
+    }
+}
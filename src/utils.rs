@@ -1,15 +1,192 @@
-// pub fn extrapolate_dimensions<T>(vec: &Vec<T>) -> Vec<usize> {
-//     let mut dimensions = Vec::new();
-//     let mut current_level = vec;
-//
-//     while let Some(first_element) = current_level.first() {
-//         dimensions.push(current_level.len());
-//         if let Some(next_level) = first_element.downcast_ref::<Vec<T>>() {
-//             current_level = next_level;
-//         } else {
-//             break;
-//         }
-//     }
-//
-//     dimensions
-// }
\ No newline at end of file
+use crate::dtype::Element;
+use crate::error::Error;
+use std::ops::Range;
+
+/// Nested host data (`Vec<Vec<T>>`, `[[T; N]; M]`, slices, ...) whose shape can be inferred.
+/// Every level must be rectangular: all children of a level need the same shape.
+///
+/// Unsuffixed float literals default to `f64` in Rust; those are stored as `f32`.
+pub trait Nested {
+    type Elem: Element;
+
+    /// Returns the length of every nesting level, outermost first.
+    fn shape(&self) -> Result<Vec<usize>, Error>;
+
+    /// Appends every element to `out` in row-major order.
+    fn flatten_into(&self, out: &mut Vec<Self::Elem>);
+}
+
+macro_rules! impl_nested_scalar {
+    ($t:ty => $elem:ty) => {
+        impl Nested for $t {
+            type Elem = $elem;
+
+            fn shape(&self) -> Result<Vec<usize>, Error> {
+                Ok(Vec::new())
+            }
+
+            #[allow(clippy::unnecessary_cast)]
+            fn flatten_into(&self, out: &mut Vec<Self::Elem>) {
+                out.push(*self as $elem);
+            }
+        }
+    };
+}
+
+impl_nested_scalar!(f32 => f32);
+impl_nested_scalar!(f64 => f32);
+impl_nested_scalar!(u32 => u32);
+impl_nested_scalar!(i32 => i32);
+
+impl<N: Nested> Nested for [N] {
+    type Elem = N::Elem;
+
+    fn shape(&self) -> Result<Vec<usize>, Error> {
+        extrapolate_dimensions(self)
+    }
+
+    fn flatten_into(&self, out: &mut Vec<Self::Elem>) {
+        for child in self {
+            child.flatten_into(out);
+        }
+    }
+}
+
+impl<N: Nested, const K: usize> Nested for [N; K] {
+    type Elem = N::Elem;
+
+    fn shape(&self) -> Result<Vec<usize>, Error> {
+        extrapolate_dimensions(self)
+    }
+
+    fn flatten_into(&self, out: &mut Vec<Self::Elem>) {
+        self.as_slice().flatten_into(out)
+    }
+}
+
+impl<N: Nested> Nested for Vec<N> {
+    type Elem = N::Elem;
+
+    fn shape(&self) -> Result<Vec<usize>, Error> {
+        extrapolate_dimensions(self)
+    }
+
+    fn flatten_into(&self, out: &mut Vec<Self::Elem>) {
+        self.as_slice().flatten_into(out)
+    }
+}
+
+impl<N: Nested + ?Sized> Nested for &N {
+    type Elem = N::Elem;
+
+    fn shape(&self) -> Result<Vec<usize>, Error> {
+        (**self).shape()
+    }
+
+    fn flatten_into(&self, out: &mut Vec<Self::Elem>) {
+        (**self).flatten_into(out)
+    }
+}
+
+/// Infers the dimensions of one nesting level and everything below it, rejecting ragged data.
+pub fn extrapolate_dimensions<N: Nested>(level: &[N]) -> Result<Vec<usize>, Error> {
+    let mut dimensions = vec![level.len()];
+    let Some(first) = level.first() else {
+        return Ok(dimensions);
+    };
+
+    let inner = first.shape()?;
+    for (i, child) in level.iter().enumerate().skip(1) {
+        let shape = child.shape()?;
+        if shape != inner {
+            return Err(Error::InvalidArgument(format!(
+                "ragged nested data: element {} has shape {:?} but element 0 has shape {:?}",
+                i, shape, inner
+            )));
+        }
+    }
+    dimensions.extend(inner);
+
+    Ok(dimensions)
+}
+
+/// Checks that `len` elements exactly fill `dimensions`.
+pub fn check_shape(dimensions: &[usize; 4], len: usize) -> Result<(), Error> {
+    let expected = dimensions.iter().product();
+    if len != expected {
+        return Err(Error::ShapeMismatch {
+            dimensions: *dimensions,
+            expected,
+            actual: len,
+        });
+    }
+
+    Ok(())
+}
+
+/// Infers the dimensions of nested data, padded to 4 with trailing ones, and flattens it in row-major order.
+pub fn flatten<N: Nested>(data: N) -> Result<([usize; 4], Vec<N::Elem>), Error> {
+    let shape = data.shape()?;
+    if shape.len() > 4 {
+        return Err(Error::InvalidArgument(format!("arrays have at most 4 dimensions, but the data is nested {} deep", shape.len())));
+    }
+    let mut dimensions = [1; 4];
+    dimensions[..shape.len()].copy_from_slice(&shape);
+
+    let mut flat = Vec::with_capacity(dimensions.iter().product());
+    data.flatten_into(&mut flat);
+
+    Ok((dimensions, flat))
+}
+
+/// Checks that `len` elements from `offset` on lie within an array of `total` elements, returning their range.
+pub fn check_range(offset: usize, len: usize, total: usize) -> Result<Range<usize>, Error> {
+    match offset.checked_add(len) {
+        Some(end) if end <= total => Ok(offset..end),
+        _ => Err(Error::InvalidArgument(format!(
+            "{} elements from offset {} don't fit into an array of {} elements",
+            len, offset, total
+        ))),
+    }
+}
+
+/// Checks that every range lies within its dimension, returning the dimensions of the region they select.
+pub fn check_ranges(dimensions: &[usize; 4], ranges: &[Range<usize>; 4]) -> Result<[usize; 4], Error> {
+    let mut region = [0; 4];
+    for (axis, (range, &dimension)) in ranges.iter().zip(dimensions).enumerate() {
+        if range.start > range.end || range.end > dimension {
+            return Err(Error::InvalidArgument(format!(
+                "range {:?} is out of bounds for dimension {} of length {}",
+                range, axis, dimension
+            )));
+        }
+        region[axis] = range.len();
+    }
+
+    Ok(region)
+}
+
+/// The runs of consecutive elements that `ranges` select from an array of `dimensions`, in row-major order.
+/// Adjacent runs are merged, so a region of whole rows comes out as a single run.
+pub fn runs(dimensions: &[usize; 4], ranges: &[Range<usize>; 4]) -> Vec<Range<usize>> {
+    let [_, d1, d2, d3] = *dimensions;
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    if ranges.iter().any(|range| range.is_empty()) {
+        return runs;
+    }
+
+    for i0 in ranges[0].clone() {
+        for i1 in ranges[1].clone() {
+            for i2 in ranges[2].clone() {
+                let start = ((i0 * d1 + i1) * d2 + i2) * d3 + ranges[3].start;
+                let run = start..start + ranges[3].len();
+                match runs.last_mut() {
+                    Some(last) if last.end == run.start => last.end = run.end,
+                    _ => runs.push(run),
+                }
+            }
+        }
+    }
+
+    runs
+}
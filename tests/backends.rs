@@ -0,0 +1,122 @@
+//! Runs every built-in operation on each backend and checks they agree, the CPU backend being the reference.
+
+mod common;
+
+use common::contexts;
+use luma::{Array, Context, Error};
+
+/// Runs `f` on every backend, checking each result against `expected`.
+async fn check<T, F>(expected: Vec<T>, f: impl Fn(Context) -> F)
+where
+    T: PartialEq + std::fmt::Debug,
+    F: std::future::Future<Output = Vec<T>>,
+{
+    for ctx in contexts().await {
+        let backend = ctx.backend();
+        assert_eq!(f(ctx).await, expected, "{:?}", backend);
+    }
+}
+
+#[tokio::test]
+async fn double() {
+    let values = (0..1000u32).collect::<Vec<_>>();
+    let expected = values.iter().map(|value| value * 2).collect();
+    check(expected, |ctx| {
+        let values = values.clone();
+        async move {
+            let mut array = ctx.array(&[values.len(), 1, 1, 1], &values).await.unwrap();
+            array.double_test().await.unwrap()
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn fill() {
+    check(vec![-2.5f32; 3 * 5 * 7], |ctx| async move {
+        ctx.full(&[3, 5, 7, 1], -2.5f32).await.unwrap().to_vec::<f32>().await.unwrap()
+    })
+    .await;
+    check(vec![-7i32; 100], |ctx| async move {
+        ctx.full(&[100, 1, 1, 1], -7i32).await.unwrap().to_vec::<i32>().await.unwrap()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn ramp() {
+    check((0..50).map(|i| 1.0 + i as f32 * 0.5).collect(), |ctx| async move {
+        ctx.arange(1.0f32, 26.0, 0.5).await.unwrap().to_vec::<f32>().await.unwrap()
+    })
+    .await;
+    check((0..20).map(|i| 10 - 3 * i).collect(), |ctx| async move {
+        ctx.arange(10i32, -50, -3).await.unwrap().to_vec::<i32>().await.unwrap()
+    })
+    .await;
+    check((0..64).map(|i| 5 + 2 * i).collect(), |ctx| async move {
+        ctx.arange(5u32, 133, 2).await.unwrap().to_vec::<u32>().await.unwrap()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn eye() {
+    let n = 37;
+    let expected = (0..n * n).map(|i| if i / n == i % n { 1.0f32 } else { 0.0 }).collect();
+    check(expected, |ctx| async move { ctx.eye::<f32>(n).await.unwrap().to_vec::<f32>().await.unwrap() }).await;
+}
+
+#[tokio::test]
+async fn oversized_constructors_are_rejected() {
+    for ctx in contexts().await {
+        let result = ctx.eye::<f32>(70_000).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))), "{:?}: {:?}", ctx.backend(), result.err());
+        let result = ctx.arange(0.0f32, 5e9, 1.0).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))), "{:?}: {:?}", ctx.backend(), result.err());
+    }
+}
+
+#[tokio::test]
+async fn tile() {
+    let (nx, ny) = (13, 9);
+    let expected = (0..ny)
+        .flat_map(|y| (0..nx).map(move |x| (x as u32, 100 + y as u32)))
+        .collect::<Vec<_>>();
+    check(expected, |ctx| async move {
+        let x = ctx.arange(0u32, nx as u32, 1).await.unwrap();
+        let y = ctx.arange(100u32, 100 + ny as u32, 1).await.unwrap();
+        let (xs, ys) = Array::meshgrid(&x, &y).await.unwrap();
+        let xs = xs.to_vec::<u32>().await.unwrap();
+        let ys = ys.to_vec::<u32>().await.unwrap();
+        xs.into_iter().zip(ys).collect()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn meshgrid_rejects_arrays_that_are_not_one_dimensional() {
+    for ctx in contexts().await {
+        let x = ctx.arange(0u32, 6, 1).await.unwrap();
+        let grid = ctx.zeros::<u32>(&[2, 3, 1, 1]).await.unwrap();
+        let result = Array::meshgrid(&x, &grid).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))), "{:?}: {:?}", ctx.backend(), result.err());
+        let result = Array::meshgrid(&grid, &x).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))), "{:?}: {:?}", ctx.backend(), result.err());
+    }
+}
+
+#[tokio::test]
+async fn fused_maps() {
+    let values = (0..500).map(|i| i as f32 - 250.0).collect::<Vec<_>>();
+    let expected = values.iter().map(|&a| (a * 2.0 + 1.0) * (a * 2.0 + 1.0) - a).collect();
+    check(expected, |ctx| {
+        let values = values.clone();
+        async move {
+            let a = ctx.array(&[values.len(), 1, 1, 1], &values).await.unwrap();
+            let b = a.map("x * 2.0 + 1.0").await.unwrap();
+            let c = b.multiply(&b).await.unwrap();
+            c.subtract(&a).await.unwrap().to_vec::<f32>().await.unwrap()
+        }
+    })
+    .await;
+}
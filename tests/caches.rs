@@ -0,0 +1,28 @@
+mod common;
+
+use common::contexts_with;
+
+#[tokio::test]
+async fn map_caches_stay_bounded() {
+    for ctx in contexts_with(|builder| builder.map_cache_capacity(4)).await {
+        let a = ctx.arange(0.0f32, 64.0, 1.0).await.unwrap();
+        let mut full = None;
+        for i in 0..40 {
+            let b = a.map(&format!("x * {}.0", i)).await.unwrap();
+            assert_eq!(b.to_vec::<f32>().await.unwrap()[3], 3.0 * i as f32);
+
+            let stats = ctx.cache_stats();
+            assert!(stats.map_kernels <= 4, "{:?}", stats);
+            assert!(stats.fused_kernels <= 4, "{:?}", stats);
+            // Once the caches are full, every new kernel replaces an old one.
+            match full {
+                None if i == 10 => full = Some(stats),
+                None => {}
+                Some(full) => {
+                    assert!(stats.shaders <= full.shaders, "{:?} after {:?}", stats, full);
+                    assert!(stats.pipelines <= full.pipelines, "{:?} after {:?}", stats, full);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::contexts;
+
+#[tokio::test]
+async fn clone_then_mutate() {
+    for ctx in contexts().await {
+        let mut a = ctx.array(&[4, 1, 1, 1], &[1u32, 2, 3, 4]).await.unwrap();
+        let mut b = a.clone();
+
+        b.write(1, &[20u32, 30]).await.unwrap();
+        assert_eq!(a.to_vec::<u32>().await.unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(b.to_vec::<u32>().await.unwrap(), vec![1, 20, 30, 4]);
+
+        a.double_test().await.unwrap();
+        assert_eq!(a.to_vec::<u32>().await.unwrap(), vec![2, 4, 6, 8]);
+        assert_eq!(b.to_vec::<u32>().await.unwrap(), vec![1, 20, 30, 4]);
+    }
+}
+
+#[tokio::test]
+async fn mutate_then_clone() {
+    for ctx in contexts().await {
+        let mut a = ctx.array(&[2, 2, 1, 1], &[1.0f32, 2.0, 3.0, 4.0]).await.unwrap();
+        a.write_slice(&[0..1, 0..2, 0..1, 0..1], &[-1.0f32, -2.0]).await.unwrap();
+        let mut b = a.clone();
+        assert_eq!(b.to_vec::<f32>().await.unwrap(), vec![-1.0, -2.0, 3.0, 4.0]);
+
+        a.write(3, &[40.0f32]).await.unwrap();
+        b.write(0, &[10.0f32]).await.unwrap();
+        assert_eq!(a.to_vec::<f32>().await.unwrap(), vec![-1.0, -2.0, 3.0, 40.0]);
+        assert_eq!(b.to_vec::<f32>().await.unwrap(), vec![10.0, -2.0, 3.0, 4.0]);
+    }
+}
+
+#[tokio::test]
+async fn clones_of_pending_expressions() {
+    for ctx in contexts().await {
+        let a = ctx.array(&[3, 1, 1, 1], &[1i32, 2, 3]).await.unwrap();
+        let mut b = a.map("x * 10").await.unwrap();
+        let c = b.clone();
+
+        b.write(0, &[0i32]).await.unwrap();
+        assert_eq!(b.to_vec::<i32>().await.unwrap(), vec![0, 20, 30]);
+        assert_eq!(c.to_vec::<i32>().await.unwrap(), vec![10, 20, 30]);
+        assert_eq!(a.to_vec::<i32>().await.unwrap(), vec![1, 2, 3]);
+    }
+}
+
+#[tokio::test]
+async fn deep_copies_are_independent() {
+    for ctx in contexts().await {
+        let mut a = ctx.arange(0u32, 6, 1).await.unwrap();
+        let mut b = a.deep_copy().await.unwrap();
+        assert!(b.is_evaluated());
+
+        a.write(0, &[100u32]).await.unwrap();
+        b.double_test().await.unwrap();
+        assert_eq!(a.to_vec::<u32>().await.unwrap(), vec![100, 1, 2, 3, 4, 5]);
+        assert_eq!(b.to_vec::<u32>().await.unwrap(), vec![0, 2, 4, 6, 8, 10]);
+
+        let storage = ctx.memory_stats().storage;
+        let c = b.deep_copy().await.unwrap();
+        assert!(ctx.memory_stats().storage > storage);
+        drop(b);
+        assert_eq!(c.to_vec::<u32>().await.unwrap(), vec![0, 2, 4, 6, 8, 10]);
+    }
+}
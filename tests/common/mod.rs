@@ -0,0 +1,21 @@
+// Each test crate includes this module but only uses some of it.
+#![allow(dead_code)]
+
+use luma::{BackendKind, Context, ContextBuilder, Error};
+
+/// A context on the CPU backend, followed by one on the GPU if there is an adapter.
+pub async fn contexts() -> Vec<Context> {
+    contexts_with(|builder| builder).await
+}
+
+/// Like [contexts], with further settings applied to both builders.
+pub async fn contexts_with(configure: impl Fn(ContextBuilder) -> ContextBuilder) -> Vec<Context> {
+    let mut contexts = vec![configure(Context::builder().backend(BackendKind::Cpu)).build().await.unwrap()];
+    match configure(Context::builder().backend(BackendKind::Gpu)).build().await {
+        Ok(ctx) => contexts.push(ctx),
+        Err(Error::NoAdapter) => {}
+        Err(error) => panic!("{}", error),
+    }
+
+    contexts
+}
@@ -0,0 +1,70 @@
+use luma::{BackendKind, Context, Error, Grid, Kernel};
+
+const SCALE: &str = r#"
+    override WORKGROUP_SIZE: u32 = 64;
+    @group(0) @binding(0) var<storage, read> input: array<f32>;
+    @group(0) @binding(1) var<storage, read_write> output: array<f32>;
+    @group(0) @binding(2) var<uniform> len: vec4<u32>;
+
+    @compute @workgroup_size(WORKGROUP_SIZE)
+    fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+        if (id.x < len.x) {
+            output[id.x] = input[id.x] * 3.0;
+        }
+    }
+"#;
+
+// Binds its input read-write while the kernel declares it read-only, so its pipeline fails validation.
+const BROKEN: &str = r#"
+    override WORKGROUP_SIZE: u32 = 64;
+    @group(0) @binding(0) var<storage, read_write> input: array<f32>;
+    @group(0) @binding(1) var<storage, read_write> output: array<f32>;
+    @group(0) @binding(2) var<uniform> len: vec4<u32>;
+
+    @compute @workgroup_size(WORKGROUP_SIZE)
+    fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+        if (id.x < len.x) {
+            output[id.x] = input[id.x];
+        }
+    }
+"#;
+
+async fn scale(ctx: &Context, name: &str, values: &[f32]) -> Result<Vec<f32>, Error> {
+    let input = ctx.array(&[values.len(), 1, 1, 1], values).await?;
+    let mut output = ctx.zeros::<f32>(&[values.len(), 1, 1, 1]).await?;
+    ctx.run_kernel(name, &[&input], &mut [&mut output], &[values.len() as u32], Grid::Elements(values.len()))
+        .await?;
+
+    output.to_vec::<f32>().await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_errors_are_reported_by_the_operation_that_caused_them() {
+    let ctx = match Context::builder().backend(BackendKind::Gpu).build().await {
+        Ok(ctx) => ctx,
+        Err(Error::NoAdapter) => return,
+        Err(error) => panic!("{}", error),
+    };
+    ctx.register_kernel(Kernel::new("scale", SCALE).inputs(1).outputs(1).uniform(true)).await.unwrap();
+    ctx.register_kernel(Kernel::new("broken", BROKEN).inputs(1).outputs(1).uniform(true)).await.unwrap();
+
+    let mut tasks = Vec::new();
+    for task in 0..16 {
+        let ctx = ctx.clone();
+        tasks.push(tokio::spawn(async move {
+            let values = (0..256).map(|i| (i + task) as f32).collect::<Vec<_>>();
+            let name = if task == 7 { "broken" } else { "scale" };
+            (task, values.clone(), scale(&ctx, name, &values).await)
+        }));
+    }
+
+    for task in tasks {
+        let (task, values, result) = task.await.unwrap();
+        if task == 7 {
+            assert!(matches!(result, Err(Error::Validation(_))), "{:?}", result);
+        } else {
+            let expected = values.iter().map(|value| value * 3.0).collect::<Vec<_>>();
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+}
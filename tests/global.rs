@@ -0,0 +1,13 @@
+use luma::Context;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_first_calls_share_one_context() {
+    let tasks = (0..16).map(|_| tokio::spawn(Context::global())).collect::<Vec<_>>();
+    let mut contexts = Vec::new();
+    for task in tasks {
+        contexts.push(task.await.unwrap().unwrap());
+    }
+
+    assert!(contexts.iter().all(|ctx| ctx.same_device(&contexts[0])));
+    assert!(contexts[0].same_device(&Context::global().await.unwrap()));
+}